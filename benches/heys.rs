@@ -0,0 +1,62 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use cryptanalysis::heys::{brute_force_k5, full_plaintext_space, Block, HeysCipher};
+
+const ROUND_KEYS: [u16; 5] = [0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e];
+
+fn bench_encrypt(c: &mut Criterion) {
+    let cipher = HeysCipher::from_keys(&ROUND_KEYS).unwrap();
+    let pt = Block::new(0xbeef);
+    c.bench_function("encrypt", |b| b.iter(|| cipher.encrypt(black_box(&pt))));
+}
+
+fn bench_encrypt_codebook(c: &mut Criterion) {
+    let cipher = HeysCipher::from_keys(&ROUND_KEYS).unwrap();
+    c.bench_function("encrypt_codebook", |b| {
+        b.iter(|| black_box(&cipher).encrypt_codebook())
+    });
+}
+
+fn bench_brute_force_k5(c: &mut Criterion) {
+    let cipher = HeysCipher::from_keys(&ROUND_KEYS).unwrap();
+    let plaintexts = full_plaintext_space()
+        .into_iter()
+        .step_by(65)
+        .take(1000)
+        .collect::<Vec<Block>>();
+    let ciphertexts = cipher.encrypt_blocks(&plaintexts).unwrap();
+    let mut group = c.benchmark_group("brute_force_k5");
+    group.sample_size(10);
+    group.bench_function("1000 pairs", |b| {
+        b.iter(|| {
+            brute_force_k5(
+                black_box(&plaintexts),
+                black_box(&ciphertexts),
+                &[5, 7, 8],
+                &[6, 8, 14, 16],
+                [0; 4],
+            )
+        })
+    });
+    let codebook = cipher.encrypt_codebook().unwrap();
+    let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) = codebook.into_iter().unzip();
+    group.bench_function("65536 pairs", |b| {
+        b.iter(|| {
+            brute_force_k5(
+                black_box(&plaintexts),
+                black_box(&ciphertexts),
+                &[5, 7, 8],
+                &[6, 8, 14, 16],
+                [0; 4],
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_encrypt,
+    bench_encrypt_codebook,
+    bench_brute_force_k5
+);
+criterion_main!(benches);
@@ -0,0 +1,55 @@
+//! The last-round attack as a single call, from PT-CT files to ranked K5
+//! candidates
+use crate::heys::brute_force_k5;
+use crate::io::read_blocks_auto;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+type Result<T> = core::result::Result<T, Box<dyn Error>>;
+
+/// What to attack and with which linear approximation
+#[derive(Debug, Clone)]
+pub struct AttackConfig {
+    /// File with one plaintext per line, as binary or hex strings
+    pub plaintexts_path: String,
+    /// File with one ciphertext per line, in the same format
+    pub ciphertexts_path: String,
+    /// Plaintext bit locs (big-endian, 1-based)
+    pub pt_locs: Vec<u8>,
+    /// Bit locs of the state entering the last S-box layer
+    pub u4_locs: Vec<u8>,
+    /// Number of candidates to keep
+    pub top: usize,
+}
+
+/// The best K5 candidates of an attack and what it took to find them
+#[derive(Debug, Clone)]
+pub struct AttackResult {
+    /// The `top` (signed bias, K5) pairs, ranked by bias magnitude
+    pub rankings: Vec<(f64, u16)>,
+    /// Time spent reading the files and ranking the candidates
+    pub elapsed: Duration,
+    /// Number of PT-CT pairs the biases were computed over
+    pub pairs: usize,
+}
+
+/// Read both files, rank every K5 candidate with `brute_force_k5`, and keep
+/// the best `top`
+pub fn run_attack(config: AttackConfig) -> Result<AttackResult> {
+    let start = Instant::now();
+    let plaintexts = read_blocks_auto(&config.plaintexts_path)?;
+    let ciphertexts = read_blocks_auto(&config.ciphertexts_path)?;
+    let mut rankings = brute_force_k5(
+        &plaintexts,
+        &ciphertexts,
+        &config.pt_locs,
+        &config.u4_locs,
+        [0; 4],
+    )?;
+    rankings.truncate(config.top);
+    return Ok(AttackResult {
+        rankings,
+        elapsed: start.elapsed(),
+        pairs: plaintexts.len(),
+    });
+}
@@ -0,0 +1,129 @@
+//! Difference distribution table (DDT) of a 4-bit S-box
+use alloc::vec::Vec;
+
+/// For every input difference `dx` and output difference `dy`, the number of
+/// inputs `X` out of 16 for which `S(X) ^ S(X ^ dx) == dy`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DifferenceDistributionTable {
+    counts: [[u16; 16]; 16],
+}
+
+impl DifferenceDistributionTable {
+    pub fn from_sbox(sbox: &[u16; 16]) -> Self {
+        let mut counts = [[0u16; 16]; 16];
+        for (input_diff, row) in counts.iter_mut().enumerate() {
+            for x in 0..16usize {
+                let output_diff = sbox[x] ^ sbox[x ^ input_diff];
+                row[(output_diff % 16) as usize] += 1;
+            }
+        }
+        return Self { counts };
+    }
+
+    /// The raw count; differences are taken modulo 16
+    pub fn count(&self, input_diff: u16, output_diff: u16) -> u16 {
+        return self.counts[(input_diff % 16) as usize][(output_diff % 16) as usize];
+    }
+
+    /// The probability that the input difference leads to the output difference
+    pub fn probability(&self, input_diff: u16, output_diff: u16) -> f64 {
+        return self.count(input_diff, output_diff) as f64 / 16.0;
+    }
+
+    /// The largest count over every non-zero input difference and any output
+    /// difference. The probability of the best differential through the S-box
+    /// is this over 16, so lower is more resistant to differential
+    /// cryptanalysis.
+    pub fn differential_uniformity(&self) -> u16 {
+        return (1u16..16)
+            .flat_map(|input_diff| (0u16..16).map(move |output_diff| (input_diff, output_diff)))
+            .map(|(input_diff, output_diff)| self.count(input_diff, output_diff))
+            .max()
+            .unwrap_or(0);
+    }
+
+    /// Whether the S-box is almost perfect nonlinear, i.e. has the lowest
+    /// possible differential uniformity of 2. No 4-bit bijection is APN, so
+    /// this only holds for non-bijective S-boxes.
+    pub fn is_apn(&self) -> bool {
+        return self.differential_uniformity() == 2;
+    }
+
+    /// The n differentials with a non-zero input difference and the highest
+    /// probability, as (input diff, output diff, probability) sorted
+    /// descending. Ties are kept in ascending difference order.
+    pub fn best_differentials(&self, n: usize) -> Vec<(u16, u16, f64)> {
+        let mut differentials = (1u16..16)
+            .flat_map(|input_diff| (0u16..16).map(move |output_diff| (input_diff, output_diff)))
+            .map(|(input_diff, output_diff)| {
+                (
+                    input_diff,
+                    output_diff,
+                    self.probability(input_diff, output_diff),
+                )
+            })
+            .collect::<Vec<(u16, u16, f64)>>();
+        differentials.sort_by(|(_, _, prob1), (_, _, prob2)| {
+            return prob2.total_cmp(prob1);
+        });
+        differentials.truncate(n);
+        return differentials;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heys::SBOX;
+
+    #[test]
+    fn test_ddt_rows() {
+        let ddt = DifferenceDistributionTable::from_sbox(&SBOX);
+        for input_diff in 0u16..16 {
+            let row_sum = (0u16..16)
+                .map(|output_diff| ddt.count(input_diff, output_diff))
+                .sum::<u16>();
+            assert_eq!(row_sum, 16);
+        }
+        assert_eq!(ddt.probability(0, 0), 1.0);
+        // Heys section 4.2: dX = 1011 leads to dY = 0010 for 8 of 16 inputs
+        assert_eq!(ddt.count(0xB, 0x2), 8);
+    }
+
+    #[test]
+    fn test_best_differentials() {
+        let ddt = DifferenceDistributionTable::from_sbox(&SBOX);
+        let best = ddt.best_differentials(5);
+        assert_eq!(best.len(), 5);
+        assert_eq!(best[0], (0xB, 0x2, 0.5));
+        assert!(best.windows(2).all(|pair| pair[0].2 >= pair[1].2));
+        assert!(best.iter().all(|(input_diff, _, _)| *input_diff != 0));
+    }
+
+    #[test]
+    fn test_differential_uniformity() {
+        let ddt = DifferenceDistributionTable::from_sbox(&SBOX);
+        assert_eq!(ddt.differential_uniformity(), 8);
+        assert!(!ddt.is_apn());
+
+        // x^3 over GF(2^4) with the modulus x^4 + x + 1 is APN
+        let gf_mul = |mut a: u16, mut b: u16| {
+            let mut product = 0;
+            while b != 0 {
+                if b & 1 != 0 {
+                    product ^= a;
+                }
+                a <<= 1;
+                if a & 0x10 != 0 {
+                    a ^= 0x13;
+                }
+                b >>= 1;
+            }
+            return product;
+        };
+        let cube = core::array::from_fn(|x| gf_mul(gf_mul(x as u16, x as u16), x as u16));
+        let ddt = DifferenceDistributionTable::from_sbox(&cube);
+        assert_eq!(ddt.differential_uniformity(), 2);
+        assert!(ddt.is_apn());
+    }
+}
@@ -0,0 +1,173 @@
+//! The error type of the cipher, the bias computations, and the key searches
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::num::ParseIntError;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A string that does not describe a block, with what was wrong with it
+    Parse(String),
+    /// A word with bits set above the block width
+    WordTooWide { word: u32, bits: usize },
+    /// A single bit loc outside `first..=last`
+    LocOutOfRange {
+        loc: usize,
+        first: usize,
+        last: usize,
+    },
+    /// A loc outside 1 to 16 in the list of locs passed as `name`
+    InvalidLocs { name: &'static str, loc: u8 },
+    /// An empty list of locs passed as `name`
+    EmptyLocs { name: &'static str },
+    /// A bit value other than 0 or 1
+    InvalidBitValue(u16),
+    /// Not as many ciphertexts as plaintexts
+    LengthMismatch {
+        plaintexts: usize,
+        ciphertexts: usize,
+    },
+    /// No PT-CT pairs at all
+    NoPairs,
+    /// A sample larger than the PT-CT pairs it is drawn from
+    SampleTooLarge { sample_size: usize, pairs: usize },
+    /// A measurement over zero random samples
+    NoSamples,
+    /// A confidence level outside 0 to 1
+    InvalidConfidence(f64),
+    /// An S-box output of 16 or more
+    SboxOutputOutOfRange { input: u16, output: u16 },
+    /// An S-box that maps more than one input to `output`
+    SboxNotBijective { output: u16 },
+    /// An S-box that maps no input to `output`
+    SboxNoPreimage { output: u16 },
+    /// A permutation that maps a bit shift past the block width
+    PermutationOutOfRange { from: usize, to: usize, bits: usize },
+    /// A permutation that maps more than one bit shift to `to`
+    PermutationNotBijective { to: usize },
+    /// A cipher without any rounds
+    NoRounds,
+    /// A number of round keys other than `rounds + 1`
+    RoundKeyCount { rounds: usize, keys: usize },
+    /// Fewer than the 2 round keys of a 1-round cipher
+    TooFewRoundKeys { keys: usize },
+    /// More rounds than the cipher has
+    TooManyRounds { rounds: usize, cipher_rounds: usize },
+    /// A trail that does not end where the cipher's last round begins
+    TrailLength {
+        trail_rounds: usize,
+        cipher_rounds: usize,
+    },
+    /// A trail the key recovery has no stage for
+    UnsupportedTrail { rounds: usize },
+    /// No trail of a recovery stage reaches the 1-based `sbox`
+    MissingTrail { rounds: usize, sbox: usize },
+    /// No combination of candidate round keys passes the consistency check
+    NoConsistentKeys,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Self::Parse(message) => write!(f, "{message}"),
+            Self::WordTooWide { word, bits } => write!(f, "0x{word:x} does not fit in {bits} bits"),
+            Self::LocOutOfRange { loc, first, last } => {
+                write!(f, "loc must be between {first} and {last} but got {loc}")
+            }
+            Self::InvalidLocs { name, loc } => {
+                write!(f, "{name} contains {loc}, but locs must be between 1 and 16")
+            }
+            Self::EmptyLocs { name } => write!(f, "{name} must not be empty"),
+            Self::InvalidBitValue(value) => write!(f, "bit value must be 0 or 1 but got {value}"),
+            Self::LengthMismatch {
+                plaintexts,
+                ciphertexts,
+            } => write!(f, "{plaintexts} plaintexts but {ciphertexts} ciphertexts"),
+            Self::NoPairs => write!(f, "Expected at least one PT-CT pair"),
+            Self::SampleTooLarge { sample_size, pairs } => {
+                write!(f, "Cannot sample {sample_size} out of {pairs} PT-CT pairs")
+            }
+            Self::NoSamples => write!(f, "Cannot measure over zero samples"),
+            Self::InvalidConfidence(confidence) => {
+                write!(f, "confidence must be between 0 and 1 but got {confidence}")
+            }
+            Self::SboxOutputOutOfRange { input, output } => {
+                write!(f, "S-box maps {input} to {output}, which is not below 16")
+            }
+            Self::SboxNotBijective { output } => {
+                write!(f, "S-box maps more than one input to {output}")
+            }
+            Self::SboxNoPreimage { output } => write!(f, "No input nibble maps to {output}"),
+            Self::PermutationOutOfRange { from, to, bits } => {
+                write!(f, "Permutation maps shift {from} to {to}, which is not below {bits}")
+            }
+            Self::PermutationNotBijective { to } => {
+                write!(f, "Permutation maps more than one shift to {to}")
+            }
+            Self::NoRounds => write!(f, "Cipher requires at least 1 round"),
+            Self::RoundKeyCount { rounds, keys } => {
+                write!(f, "{rounds} rounds require {} round keys but got {keys}", rounds + 1)
+            }
+            Self::TooFewRoundKeys { keys } => {
+                write!(f, "Cipher requires at least 2 round keys but got {keys}")
+            }
+            Self::TooManyRounds {
+                rounds,
+                cipher_rounds,
+            } => write!(f, "Cannot run {rounds} rounds of a {cipher_rounds}-round cipher"),
+            Self::TrailLength {
+                trail_rounds,
+                cipher_rounds,
+            } => write!(
+                f,
+                "A {trail_rounds}-round trail cannot be checked against a {cipher_rounds}-round cipher"
+            ),
+            Self::UnsupportedTrail { rounds } => {
+                write!(f, "Expected 1 to 3 round trails but got {rounds}")
+            }
+            Self::MissingTrail { rounds, sbox } => {
+                write!(f, "No {rounds}-round trail activates S-box {sbox}")
+            }
+            Self::NoConsistentKeys => {
+                write!(f, "No candidate round keys are consistent with the PT-CT pairs")
+            }
+        };
+    }
+}
+
+impl core::error::Error for Error {}
+
+impl From<ParseIntError> for Error {
+    fn from(err: ParseIntError) -> Self {
+        return Self::Parse(err.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+
+    #[test]
+    fn test_error_display() {
+        let err = Error::LengthMismatch {
+            plaintexts: 3,
+            ciphertexts: 2,
+        };
+        assert_eq!(err.to_string(), "3 plaintexts but 2 ciphertexts");
+        assert_eq!(
+            Error::RoundKeyCount { rounds: 4, keys: 4 }.to_string(),
+            "4 rounds require 5 round keys but got 4"
+        );
+        let parsed = "1x".parse::<u16>().unwrap_err();
+        assert_eq!(
+            Error::from(parsed.clone()),
+            Error::Parse(parsed.to_string())
+        );
+
+        // Converts into the boxed errors of the std-only modules
+        let boxed: Box<dyn core::error::Error + Send + Sync> = Box::new(Error::NoPairs);
+        assert_eq!(boxed.to_string(), "Expected at least one PT-CT pair");
+    }
+}
@@ -1,326 +1,3141 @@
 //! Implementation of the Heys' Cipher
-use std::error::Error;
-
-type Result<T> = core::result::Result<T, Box<dyn Error>>;
-
-const SBOX: [(u16, u16); 16] = [
-    (0x0, 0xE),
-    (0x1, 0x4),
-    (0x2, 0xD),
-    (0x3, 0x1),
-    (0x4, 0x2),
-    (0x5, 0xF),
-    (0x6, 0xB),
-    (0x7, 0x8),
-    (0x8, 0x3),
-    (0x9, 0xA),
-    (0xA, 0x6),
-    (0xB, 0xC),
-    (0xC, 0x5),
-    (0xD, 0x9),
-    (0xE, 0x0),
-    (0xF, 0x7),
-];
+use crate::error::{Error, Result};
+use crate::keyschedule::KeySchedule;
+use crate::lat::LinearApproximationTable;
+use crate::spn::SpnCipher;
+#[cfg(feature = "std")]
+use crate::stats::wilson_interval;
+use crate::trail::{build_multiround_trail, MultiRoundTrail};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::cmp::Ordering;
+use core::fmt;
+use core::ops::BitXor;
+use log::{debug, info};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
-const SBOX_INVERT: [(u16, u16); 16] = [
-    (0xE, 0x0),
-    (0x4, 0x1),
-    (0xD, 0x2),
-    (0x1, 0x3),
-    (0x2, 0x4),
-    (0xF, 0x5),
-    (0xB, 0x6),
-    (0x8, 0x7),
-    (0x3, 0x8),
-    (0xA, 0x9),
-    (0x6, 0xA),
-    (0xC, 0xB),
-    (0x5, 0xC),
-    (0x9, 0xD),
-    (0x0, 0xE),
-    (0x7, 0xF),
+/// The S-box, indexed directly by the input nibble
+pub const SBOX: [u16; 16] = [
+    0xE, 0x4, 0xD, 0x1, 0x2, 0xF, 0xB, 0x8, 0x3, 0xA, 0x6, 0xC, 0x5, 0x9, 0x0, 0x7,
 ];
 
-const PERMUTATION: [(u16, u16); 16] = [
-    (0x8000, 0x8000),
-    (0x4000, 0x0800),
-    (0x2000, 0x0080),
-    (0x1000, 0x0008),
-    (0x0800, 0x4000),
-    (0x0400, 0x0400),
-    (0x0200, 0x0040),
-    (0x0100, 0x0004),
-    (0x0080, 0x2000),
-    (0x0040, 0x0200),
-    (0x0020, 0x0020),
-    (0x0010, 0x0002),
-    (0x0008, 0x1000),
-    (0x0004, 0x0100),
-    (0x0002, 0x0010),
-    (0x0001, 0x0001),
+/// The inverse S-box, indexed directly by the output nibble
+pub const SBOX_INVERT: [u16; 16] = [
+    0xE, 0x3, 0x4, 0x8, 0x1, 0xC, 0xA, 0xF, 0x7, 0xD, 0x9, 0x6, 0xB, 0x2, 0x0, 0x5,
 ];
 
-const PERMUTATION_INVERT: [(u16, u16); 16] = [
-    (0x8000, 0x8000),
-    (0x0800, 0x4000),
-    (0x0080, 0x2000),
-    (0x0008, 0x1000),
-    (0x4000, 0x0800),
-    (0x0400, 0x0400),
-    (0x0040, 0x0200),
-    (0x0004, 0x0100),
-    (0x2000, 0x0080),
-    (0x0200, 0x0040),
-    (0x0020, 0x0020),
-    (0x0002, 0x0010),
-    (0x1000, 0x0008),
-    (0x0100, 0x0004),
-    (0x0010, 0x0002),
-    (0x0001, 0x0001),
-];
+/// The bit permutation: the bit at shift `i` (0 is the least significant bit)
+/// is moved to shift `PERMUTATION[i]`
+pub const PERMUTATION: [usize; 16] = [0, 4, 8, 12, 1, 5, 9, 13, 2, 6, 10, 14, 3, 7, 11, 15];
 
-fn lookup(kvpairs: &[(u16, u16)], val: u16) -> Result<u16> {
-    for (from, to) in kvpairs {
-        if *from == val {
-            return Ok(*to);
-        }
+/// Heys' permutation is an involution, so the inverse is the same table
+pub const PERMUTATION_INVERT: [usize; 16] = PERMUTATION;
+
+/// How bits of a block are numbered. Big-endian conventions count from the
+/// most significant bit, as Heys does; little-endian conventions count from
+/// the least significant bit.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BitConvention {
+    BigEndian1Based,
+    BigEndian0Based,
+    LittleEndian0Based,
+    LittleEndian1Based,
+}
+
+/// A block of `BITS` bits, could be a plaintext, ciphertext, or some
+/// intermediary state. `BITS` must be a multiple of 4 between 4 and 32 so that
+/// the block splits into whole 4-bit S-box inputs; Heys' cipher uses 16.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u32", into = "u32")
+)]
+pub struct Block<const BITS: usize = 16> {
+    val: u32,
+}
+
+impl From<u16> for Block {
+    fn from(val: u16) -> Self {
+        return Self::new(val);
+    }
+}
+
+impl From<Block> for u16 {
+    fn from(block: Block) -> Self {
+        return block.value();
+    }
+}
+
+impl<const BITS: usize> TryFrom<u32> for Block<BITS> {
+    type Error = Error;
+
+    fn try_from(word: u32) -> Result<Self> {
+        return Self::from_word(word);
+    }
+}
+
+impl<const BITS: usize> From<Block<BITS>> for u32 {
+    fn from(block: Block<BITS>) -> Self {
+        return block.val;
+    }
+}
+
+impl<const BITS: usize> BitXor for Block<BITS> {
+    type Output = Self;
+
+    fn bitxor(self, other: Self) -> Self {
+        return Self {
+            val: self.val ^ other.val,
+        };
+    }
+}
+
+impl BitXor<u16> for Block {
+    type Output = Self;
+
+    fn bitxor(self, mask: u16) -> Self {
+        return Self::new(self.value() ^ mask);
+    }
+}
+
+/// Hex form padded to the block width, such as `0x1a2b`
+impl<const BITS: usize> fmt::Display for Block<BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "0x{:0width$x}", self.val, width = BITS / 4);
     }
-    return Err("Lookup failed".into());
 }
 
-/// A 16-bit block, could be a plaintext, ciphertext, or some intermediary
-/// state
-#[derive(Debug, Eq, PartialEq)]
-pub struct Block {
-    val: u16,
+/// Binary form of the inner value, so `{:016b}` pads to all 16 bits
+impl<const BITS: usize> fmt::Binary for Block<BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return fmt::Binary::fmt(&self.val, f);
+    }
 }
 
 impl Block {
     pub fn new(val: u16) -> Self {
-        return Self { val };
+        return Self { val: val as u32 };
+    }
+
+    pub fn value(&self) -> u16 {
+        return self.val as u16;
     }
 
     /// Get block from some 16-character binary string
     pub fn from_binstr(binstr: &str) -> Result<Self> {
+        let len = binstr.chars().count();
+        if len != 16 {
+            return Err(Error::Parse(format!(
+                "Expected 16 binary digits but got {len}: {binstr:?}"
+            )));
+        }
+        if binstr.chars().any(|c| c != '0' && c != '1') {
+            return Err(Error::Parse(format!(
+                "Expected only '0' and '1': {binstr:?}"
+            )));
+        }
         let val = u16::from_str_radix(binstr, 2)?;
         return Ok(Self::new(val));
     }
 
+    /// Get block from some 4-character hex string, in either case
+    pub fn from_hexstr(hexstr: &str) -> Result<Self> {
+        let len = hexstr.chars().count();
+        if len != 4 {
+            return Err(Error::Parse(format!(
+                "Expected 4 hex digits but got {len}: {hexstr:?}"
+            )));
+        }
+        if !hexstr.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::Parse(format!(
+                "Expected only hex digits: {hexstr:?}"
+            )));
+        }
+        let val = u16::from_str_radix(hexstr, 16)?;
+        return Ok(Self::new(val));
+    }
+
+    /// Get block from four hex nibbles separated by `sep`, most significant
+    /// first, such as `"a-b-c-d"` with `sep = '-'`
+    pub fn from_nibble_str(nibble_str: &str, sep: char) -> Result<Self> {
+        let parts = nibble_str.split(sep).collect::<Vec<&str>>();
+        if parts.len() != 4 {
+            return Err(Error::Parse(format!(
+                "Expected 4 nibbles separated by {sep:?} but got {}: {nibble_str:?}",
+                parts.len()
+            )));
+        }
+        let mut nibbles = [0; 4];
+        for (nibble, part) in nibbles.iter_mut().zip(parts) {
+            if part.is_empty() || !part.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(Error::Parse(format!(
+                    "Expected a hex nibble but got {part:?}"
+                )));
+            }
+            *nibble = u16::from_str_radix(part, 16)?;
+            if *nibble >= 16 {
+                return Err(Error::Parse(format!("Nibble {part:?} is not below 16")));
+            }
+        }
+        return Ok(Self::from_nibbles(nibbles));
+    }
+
+    /// Interpret two bytes as a big-endian block
+    pub fn from_be_bytes(bytes: [u8; 2]) -> Self {
+        return Self::new(u16::from_be_bytes(bytes));
+    }
+
+    /// Interpret two bytes as a little-endian block
+    pub fn from_le_bytes(bytes: [u8; 2]) -> Self {
+        return Self::new(u16::from_le_bytes(bytes));
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 2] {
+        return self.value().to_be_bytes();
+    }
+
+    pub fn to_le_bytes(&self) -> [u8; 2] {
+        return self.value().to_le_bytes();
+    }
+
+    /// The four nibbles in big-endian order, so `nibbles()[0]` is the input
+    /// of S-box 1
+    pub fn nibbles(&self) -> [u16; 4] {
+        let val = self.value();
+        return [val >> 12, (val >> 8) & 0xf, (val >> 4) & 0xf, val & 0xf];
+    }
+
+    /// Reassemble a block from its big-endian nibbles, the inverse of
+    /// `nibbles`. Only the low 4 bits of each nibble are used.
+    pub fn from_nibbles(nibbles: [u16; 4]) -> Self {
+        let val = nibbles
+            .iter()
+            .fold(0, |val, nibble| (val << 4) | (nibble & 0xf));
+        return Self::new(val);
+    }
+
+    /// Rotate the 16 bits towards the most significant end by `n` places,
+    /// which is the bit permutation moving shift `i` to `(i + n) % 16`
+    pub fn rotate_left(&self, n: u32) -> Self {
+        return Self::new(self.value().rotate_left(n));
+    }
+
+    /// Rotate the 16 bits towards the least significant end by `n` places
+    pub fn rotate_right(&self, n: u32) -> Self {
+        return Self::new(self.value().rotate_right(n));
+    }
+
     /// XOR with round key
     pub fn mix_key(&self, key: u16) -> Self {
-        return Self::new(self.val ^ key);
+        return *self ^ key;
+    }
+
+    /// XOR of the bits selected by `mask`
+    pub fn parity_of_mask(&self, mask: u16) -> u16 {
+        return ((self.val & mask as u32).count_ones() % 2) as u16;
+    }
+}
+
+impl<const BITS: usize> Block<BITS> {
+    /// All `BITS` bits set; fails to compile for unsupported block widths
+    const MASK: u32 = {
+        assert!(
+            BITS & 0b11 == 0 && 4 <= BITS && BITS <= 32,
+            "block width must be a multiple of 4 between 4 and 32"
+        );
+        u32::MAX >> (32 - BITS)
+    };
+
+    /// Block holding `word`, or an error if it does not fit in `BITS` bits
+    pub fn from_word(word: u32) -> Result<Self> {
+        if word & !Self::MASK != 0 {
+            return Err(Error::WordTooWide { word, bits: BITS });
+        }
+        return Ok(Self { val: word });
+    }
+
+    pub fn word(&self) -> u32 {
+        return self.val;
+    }
+
+    /// Format the block as a zero-padded `BITS`-character binary string, the
+    /// inverse of `from_binstr` for 16-bit blocks
+    pub fn to_binstr(&self) -> String {
+        return format!("{:0width$b}", self.val, width = BITS);
+    }
+
+    /// XOR with a key of the same width; bits above the block width are
+    /// dropped
+    pub fn mix_word(&self, key: u32) -> Self {
+        return Self {
+            val: (self.val ^ key) & Self::MASK,
+        };
     }
 
     /// Get the bit at the specified location following big-endianness and
-    /// 1-based indexing
+    /// 1-based indexing: loc 1 is the most significant bit and loc `BITS` the
+    /// least, so loc 1 of 0x8000 is 1. This is the reverse of the usual
+    /// `(val >> i) & 1`; see [`Block::get_bit`] for the other conventions.
+    ///
+    /// Out-of-range locs return an error, and also fail a debug assertion
+    /// since callers tend to unwrap the result.
     pub fn get_bit_1base(&self, loc: u8) -> Result<u16> {
-        if loc < 1 || loc > 16 {
-            return Err("loc must be between 1 and 16".into());
+        debug_assert!(
+            (1..=BITS).contains(&(loc as usize)),
+            "loc must be between 1 and {BITS} but got {loc}"
+        );
+        return self.get_bit(loc, BitConvention::BigEndian1Based);
+    }
+
+    /// Get the bits at each of the locations, big-endian and 1-based as in
+    /// [`Block::get_bit_1base`]. Every loc is checked before any bit is read,
+    /// so an out-of-range loc anywhere in the list is an error.
+    pub fn get_bits_1base(&self, locs: &[u8]) -> Result<Vec<u16>> {
+        if let Some(loc) = locs
+            .iter()
+            .find(|loc| !(1..=BITS).contains(&(**loc as usize)))
+        {
+            return Err(Error::LocOutOfRange {
+                loc: *loc as usize,
+                first: 1,
+                last: BITS,
+            });
+        }
+        return Ok(locs
+            .iter()
+            .map(|loc| ((self.val >> (BITS - *loc as usize)) & 1) as u16)
+            .collect::<Vec<u16>>());
+    }
+
+    /// Set the bit at the specified location, big-endian and 1-based, to
+    /// `value`, which must be 0 or 1
+    pub fn set_bit_1base(&mut self, loc: u8, value: u16) -> Result<()> {
+        *self = self.with_bit_1base(loc, value)?;
+        return Ok(());
+    }
+
+    /// Copy of the block with the bit at the specified location, big-endian
+    /// and 1-based, set to `value`, which must be 0 or 1
+    pub fn with_bit_1base(&self, loc: u8, value: u16) -> Result<Self> {
+        if !(1..=BITS).contains(&(loc as usize)) {
+            return Err(Error::LocOutOfRange {
+                loc: loc as usize,
+                first: 1,
+                last: BITS,
+            });
         }
-        let mask = 1u16 << (16 - loc);
-        if self.val & mask == 0 {
-            return Ok(0);
+        if value > 1 {
+            return Err(Error::InvalidBitValue(value));
         }
-        return Ok(1);
+        let mask = 1u32 << (BITS - loc as usize);
+        let val = (self.val & !mask) | (value as u32 * mask);
+        return Ok(Self { val });
     }
 
-    /// Substitute each 4-bit block according to the input SBOX, then put the
-    /// substituted blocks back together
-    fn substitute(&self, sbox: &[(u16, u16)]) -> Result<Self> {
-        let b0 = self.val % 16;
-        let b1 = (self.val >> 4) % 16;
-        let b2 = (self.val >> 8) % 16;
-        let b3 = (self.val >> 12) % 16;
+    /// Get the bit at `index` under the given bit numbering convention
+    pub fn get_bit(&self, index: u8, convention: BitConvention) -> Result<u16> {
+        let index = index as usize;
+        let (first, last) = match convention {
+            BitConvention::BigEndian1Based | BitConvention::LittleEndian1Based => (1, BITS),
+            BitConvention::BigEndian0Based | BitConvention::LittleEndian0Based => (0, BITS - 1),
+        };
+        if !(first..=last).contains(&index) {
+            return Err(Error::LocOutOfRange {
+                loc: index,
+                first,
+                last,
+            });
+        }
+        let shift = match convention {
+            BitConvention::BigEndian1Based => BITS - index,
+            BitConvention::BigEndian0Based => BITS - 1 - index,
+            BitConvention::LittleEndian0Based => index,
+            BitConvention::LittleEndian1Based => index - 1,
+        };
+        return Ok(((self.val >> shift) & 1) as u16);
+    }
+
+    /// Number of bits set
+    pub fn hamming_weight(&self) -> u32 {
+        return self.val.count_ones();
+    }
+
+    /// Number of bits that differ from `other`
+    pub fn hamming_distance(&self, other: &Self) -> u32 {
+        return (*self ^ *other).hamming_weight();
+    }
+
+    /// The `BITS` bits in big-endian order, bit 1 first
+    pub fn bits(&self) -> impl Iterator<Item = u16> {
+        let val = self.val;
+        return (0..BITS)
+            .rev()
+            .map(move |shift| ((val >> shift) & 1) as u16);
+    }
+
+    /// The `BITS` bits in big-endian order as (loc, bit) pairs with 1-based
+    /// locs
+    pub fn bits_1base_enumerated(&self) -> impl Iterator<Item = (u8, u16)> {
+        return (1u8..=BITS as u8).zip(self.bits());
+    }
 
-        let b0 = lookup(&sbox, b0)?;
-        let b1 = lookup(&sbox, b1)?;
-        let b2 = lookup(&sbox, b2)?;
-        let b3 = lookup(&sbox, b3)?;
+    /// Substitute each 4-bit block according to the input SBOX, then put the
+    /// substituted blocks back together. This works on the shifts directly
+    /// rather than `nibbles` so that it serves every block width.
+    ///
+    /// Panics through `sbox_apply` if a nibble maps to an S-box output of 16
+    /// or more, in every build profile; `try_substitute` returns an error
+    /// instead.
+    ///
+    /// ```
+    /// use cryptanalysis::heys::{Block, PERMUTATION, SBOX};
+    ///
+    /// let substituted = Block::new(0x1234).substitute(&SBOX);
+    /// assert_eq!(substituted.value(), 0x4d12);
+    /// let permuted = substituted.permute(&PERMUTATION).unwrap();
+    /// assert_eq!(permuted.value(), 0x4c16);
+    /// ```
+    pub fn substitute(&self, sbox: &[u16; 16]) -> Self {
+        let mut val: u32 = 0;
+        for shift in (0..BITS).step_by(4) {
+            let nibble = (self.val >> shift) % 16;
+            val |= (sbox_apply(sbox, nibble as u16) as u32) << shift;
+        }
+        return Self { val };
+    }
 
-        let b3 = b3 << 12;
-        let b2 = b2 << 8;
-        let b1 = b1 << 4;
+    /// `substitute` without any branch that depends on the block: each nibble
+    /// indexes the table directly, masked so that the index is always in
+    /// bounds, instead of going through the range assertion of `sbox_apply`.
+    /// This is for demonstrating timing side channels rather than hardening,
+    /// as the address of the lookup still depends on the nibble; a 16-entry
+    /// table does fit in one cache line though.
+    pub fn substitute_ct(&self, sbox: &[u16; 16]) -> Self {
+        let mut val: u32 = 0;
+        for shift in (0..BITS).step_by(4) {
+            let nibble = (self.val >> shift) & 0xf;
+            val |= ((sbox[nibble as usize] & 0xf) as u32) << shift;
+        }
+        return Self { val };
+    }
 
-        return Ok(Self::new(b3 + b2 + b1 + b0));
+    /// Like `substitute`, but errors instead of panicking on an S-box output of
+    /// 16 or more that one of the nibbles maps to
+    pub fn try_substitute(&self, sbox: &[u16; 16]) -> Result<Self> {
+        let mut val: u32 = 0;
+        for shift in (0..BITS).step_by(4) {
+            let nibble = (self.val >> shift) % 16;
+            let output = sbox[nibble as usize];
+            if output >= 16 {
+                return Err(Error::SboxOutputOutOfRange {
+                    input: nibble as u16,
+                    output,
+                });
+            }
+            val |= (output as u32) << shift;
+        }
+        return Ok(Self { val });
     }
 
     /// Permute each bit according to the input permutation
-    fn permute(&self, permutation: &[(u16, u16)]) -> Result<Self> {
-        let mut val: u16 = 0;
-
-        for shift in 0..16 {
-            let mask = 1u16 << shift;
-            if (self.val & mask) != 0 {
-                let mapped = lookup(permutation, mask)?;
-                val += mapped;
+    pub fn permute(&self, permutation: &[usize; BITS]) -> Result<Self> {
+        let mut val: u32 = 0;
+
+        for (shift, mapped) in permutation.iter().enumerate() {
+            if *mapped >= BITS {
+                return Err(Error::PermutationOutOfRange {
+                    from: shift,
+                    to: *mapped,
+                    bits: BITS,
+                });
+            }
+            if (self.val >> shift) & 1 != 0 {
+                val |= 1u32 << mapped;
             }
         }
 
-        return Ok(Self::new(val));
+        return Ok(Self { val });
+    }
+}
+
+/// Heys' block cipher: an SPN using Heys' S-box and permutation
+///
+/// The original cipher has 5 round keys and 4 rounds.
+pub type HeysCipher = SpnCipher;
+
+/// Serialized as its round keys, so only ciphers using the Heys S-box and
+/// permutation can be serialized
+#[cfg(feature = "serde")]
+impl serde::Serialize for HeysCipher {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> core::result::Result<S::Ok, S::Error> {
+        if *self.sbox() != SBOX || *self.permutation() != PERMUTATION {
+            return Err(serde::ser::Error::custom(
+                "only ciphers with the Heys S-box and permutation can be serialized",
+            ));
+        }
+        return self.round_keys().serialize(serializer);
     }
 }
 
-/// Heys' block cipher
-pub struct HeysCipher {
-    round_keys: [u16; 5],
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for HeysCipher {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> core::result::Result<Self, D::Error> {
+        let round_keys = Vec::<u16>::deserialize(deserializer)?;
+        return Self::from_keys(&round_keys).map_err(serde::de::Error::custom);
+    }
 }
 
 impl HeysCipher {
+    /// Cipher with `new_keys.len() - 1` rounds using Heys' S-box and
+    /// permutation; at least 2 round keys are required
     pub fn from_keys(new_keys: &[u16]) -> Result<Self> {
-        if new_keys.len() != 5 {
-            return Err("Cipher requires exactly 5 round keys".into());
+        if new_keys.len() < 2 {
+            return Err(Error::TooFewRoundKeys {
+                keys: new_keys.len(),
+            });
+        }
+        return Self::new(new_keys.len() - 1, &SBOX, &PERMUTATION, new_keys);
+    }
+
+    /// Reduced-round Heys cipher for attacks that are easier to follow, such
+    /// as 2 or 3 rounds. Unlike `from_keys` the number of rounds is explicit,
+    /// and there must be exactly `rounds + 1` keys. Since the attacks only peel
+    /// off the last round, they use the last of these keys.
+    pub fn reduced(rounds: usize, keys: &[u16]) -> Result<Self> {
+        return Self::new(rounds, &SBOX, &PERMUTATION, keys);
+    }
+
+    /// 4-round Heys cipher whose five round keys are drawn from a `StdRng`
+    /// seeded with `seed`, so the same seed always gives the same keys
+    pub fn random(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut round_keys = [0; 5];
+        for round_key in round_keys.iter_mut() {
+            *round_key = rng.gen();
         }
-        let mut round_keys: [u16; 5] = [0; 5];
-        for i in 0..5 {
-            round_keys[i] = new_keys[i];
+        return Self::from_keys(&round_keys).expect("5 round keys make a valid cipher");
+    }
+
+    /// Heys cipher whose five round keys are derived from `master`
+    pub fn from_master_key<S: KeySchedule>(master: u64, schedule: &S) -> Result<Self> {
+        return Self::from_keys(&schedule.round_keys(master));
+    }
+
+    /// Cipher with `round_keys.len() - 1` rounds using the input S-box and
+    /// permutation, whose inverses are derived automatically. Both must be
+    /// bijections, as checked by `validate_sbox` and `validate_permutation`.
+    pub fn with_components(
+        round_keys: &[u16],
+        sbox: &[u16; 16],
+        permutation: &[usize; 16],
+    ) -> Result<Self> {
+        if round_keys.len() < 2 {
+            return Err(Error::TooFewRoundKeys {
+                keys: round_keys.len(),
+            });
         }
-        return Ok(Self { round_keys });
+        return Self::new(round_keys.len() - 1, sbox, permutation, round_keys);
+    }
+
+    /// The strongest linear approximation from the plaintext to the state
+    /// entering the last S-box layer, as (plaintext mask, U mask, predicted
+    /// bias). The trail covers every round but the last, which the attack
+    /// peels off, and is found by the branch-and-bound search of
+    /// `build_multiround_trail` over the cipher's own S-box and permutation.
+    /// None for a 1-round cipher, which leaves no rounds to approximate.
+    pub fn best_linear_approximation(&self) -> Option<(u16, u16, f64)> {
+        let lat = LinearApproximationTable::from_sbox(self.sbox());
+        let trail = build_multiround_trail(&lat, self.permutation(), self.num_rounds() - 1)?;
+        return Some((trail.pt_mask, trail.u_mask, trail.bias));
+    }
+
+    /// The signed correlation `2 * bias` of the input linear relation over the
+    /// inputs PT-CT pairs
+    pub fn get_correlation(
+        &self,
+        plaintexts: &[Block],
+        ciphertexts: &[Block],
+        pt_locs: &[u8],
+        u4_locs: &[u8],
+    ) -> Result<f64> {
+        let bias = self.get_bias_signed(plaintexts, ciphertexts, pt_locs, u4_locs)?;
+        return Ok(bias_to_correlation(bias));
+    }
+
+    /// All 65536 plaintext-ciphertext pairs, in plaintext order
+    pub fn encrypt_codebook(&self) -> Result<Vec<(Block, Block)>> {
+        let plaintexts = full_plaintext_space();
+        let ciphertexts = self.encrypt_blocks(&plaintexts)?;
+        return Ok(plaintexts.into_iter().zip(ciphertexts).collect());
+    }
+
+    /// The pairs of `encrypt_codebook`, encrypted one at a time as the
+    /// iterator is advanced instead of all up front
+    pub fn codebook_iter(&self) -> impl Iterator<Item = Result<(Block, Block)>> + '_ {
+        return (u16::MIN..=u16::MAX).map(|val| {
+            let pt = Block::new(val);
+            return Ok((pt, self.encrypt(&pt)?));
+        });
     }
 
-    pub fn encrypt(&self, plaintext: &Block) -> Result<Block> {
-        let u1 = plaintext.mix_key(self.round_keys[0]);
-        let v1 = u1.substitute(&SBOX)?;
-        let u2 = v1.permute(&PERMUTATION)?.mix_key(self.round_keys[1]);
-        let v2 = u2.substitute(&SBOX)?;
-        let u3 = v2.permute(&PERMUTATION)?.mix_key(self.round_keys[2]);
-        let v3 = u3.substitute(&SBOX)?;
-        let u4 = v3.permute(&PERMUTATION)?.mix_key(self.round_keys[3]);
-        let v4 = u4.substitute(&SBOX)?;
+    /// `n` pairs of random plaintexts, drawn from a `StdRng` seeded with
+    /// `seed`, and their ciphertexts. Plaintexts may repeat, unlike a slice of
+    /// `encrypt_codebook`, but only the sampled pairs are encrypted.
+    pub fn sample_pairs(&self, n: usize, seed: u64) -> Result<Vec<(Block, Block)>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        return (0..n)
+            .map(|_| {
+                let pt = Block::new(rng.gen());
+                return Ok((pt, self.encrypt(&pt)?));
+            })
+            .collect::<Result<Vec<(Block, Block)>>>();
+    }
+
+    /// Encrypt `pt` and `pt ^ input_diff`, returning both ciphertexts and their
+    /// output difference
+    pub fn encrypt_pair(&self, pt: &Block, input_diff: u16) -> Result<(Block, Block, u16)> {
+        let ct1 = self.encrypt(pt)?;
+        let ct2 = self.encrypt(&(*pt ^ input_diff))?;
+        return Ok((ct1, ct2, ct1.value() ^ ct2.value()));
+    }
 
-        return Ok(v4.mix_key(self.round_keys[4]));
+    /// Over `samples` random plaintexts drawn from a seeded RNG, count how
+    /// often `input_diff` leads to `target_output_diff`
+    pub fn count_differential(
+        &self,
+        input_diff: u16,
+        target_output_diff: u16,
+        samples: usize,
+        seed: u64,
+    ) -> Result<usize> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut count = 0;
+        for _ in 0..samples {
+            let pt = Block::new(rng.gen());
+            let (_, _, output_diff) = self.encrypt_pair(&pt, input_diff)?;
+            if output_diff == target_output_diff {
+                count += 1;
+            }
+        }
+        return Ok(count);
     }
 
-    pub fn decrypt(&self, ciphertext: &Block) -> Result<Block> {
-        let v4 = ciphertext.mix_key(self.round_keys[4]);
-        let u4 = v4.substitute(&SBOX_INVERT)?;
-        let v3 = u4
-            .mix_key(self.round_keys[3])
-            .permute(&PERMUTATION_INVERT)?;
-        let u3 = v3.substitute(&SBOX_INVERT)?;
-        let v2 = u3
-            .mix_key(self.round_keys[2])
-            .permute(&PERMUTATION_INVERT)?;
-        let u2 = v2.substitute(&SBOX_INVERT)?;
-        let v1 = u2
-            .mix_key(self.round_keys[1])
-            .permute(&PERMUTATION_INVERT)?;
-        let u1 = v1.substitute(&SBOX_INVERT)?;
-        let pt = u1.mix_key(self.round_keys[0]);
+    /// The experimental DDT row of the whole cipher for `input_diff`: entry
+    /// `dy` counts the plaintexts `pt` out of all 65536 for which `pt` and
+    /// `pt ^ input_diff` encrypt to ciphertexts differing by `dy`. The codebook
+    /// is encrypted once and each pair is looked up in it.
+    ///
+    /// The counts are `usize` rather than `u16` since a zero input difference
+    /// puts all 65536 plaintexts on the zero output difference.
+    pub fn output_difference_distribution(&self, input_diff: u16) -> Result<Vec<usize>> {
+        let ciphertexts = self.encrypt_blocks(&full_plaintext_space())?;
+        let mut distribution = vec![0; 1 << 16];
+        for (pt, ct) in ciphertexts.iter().enumerate() {
+            let output_diff = ct.value() ^ ciphertexts[pt ^ input_diff as usize].value();
+            distribution[output_diff as usize] += 1;
+        }
+        return Ok(distribution);
+    }
 
-        return Ok(pt);
+    /// Average fraction of ciphertext bits that flip when one plaintext bit
+    /// flips, over every bit of `samples` random plaintexts drawn from a
+    /// `StdRng` seeded with `seed`. A cipher with full diffusion is near 1/2.
+    pub fn avalanche(&self, samples: usize, seed: u64) -> Result<f64> {
+        if samples == 0 {
+            return Err(Error::NoSamples);
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut flipped = 0;
+        for _ in 0..samples {
+            let pt = Block::new(rng.gen());
+            let ct = self.encrypt(&pt)?;
+            for shift in 0..16 {
+                let other = self.encrypt(&(pt ^ (1 << shift)))?;
+                flipped += ct.hamming_distance(&other);
+            }
+        }
+        return Ok(flipped as f64 / (samples * 16 * 16) as f64);
     }
 
     /// A linear approximation takes the form:
     ///
     /// (some plaintext bits) + (some U4 bits) = 0 (mod 2)
-    /// where U4 is the intermediary state and the bits are specified using
-    /// big-endian, 1-based indexing.
+    /// where U4 is the intermediary state entering the last round's
+    /// substitution and the bits are specified using big-endian, 1-based
+    /// indexing.
     ///
     /// For example:
     /// U[4,6] + U[4,8] + U[4,14] + U[4,16] + P[5] + P[7] + P[8] == 0
     /// (this is stated in Heys' paper in section 3.4)
     ///
-    /// User is responsible for making sure the input bit locs are valid
+    /// Only the last round key is used to peel off the last round, so the
+    /// other round keys never affect the result.
+    ///
+    /// Returns an error if any of the bit locs is outside 1 to 16
     pub fn check_linear_approx(
         &self,
         pt: &Block,
         ct: &Block,
         pt_locs: &[u8],
         u4_locs: &[u8],
+    ) -> Result<u16> {
+        let pt_mask = locs_to_mask(pt_locs)?;
+        let u4_mask = locs_to_mask(u4_locs)?;
+        return Ok(self.check_linear_approx_mask(pt, ct, pt_mask, u4_mask));
+    }
+
+    /// `check_linear_approx` with the plaintext and U4 bits selected by masks,
+    /// where bit loc `l` is `1 << (16 - l)`
+    pub fn check_linear_approx_mask(
+        &self,
+        pt: &Block,
+        ct: &Block,
+        pt_mask: u16,
+        u4_mask: u16,
     ) -> u16 {
-        let mut binsum: u16 = 0;
-        let u4 = ct
-            .mix_key(self.round_keys[4])
-            .substitute(&SBOX_INVERT)
-            .unwrap();
-        binsum += pt_locs
-            .iter()
-            .map(|loc| pt.get_bit_1base(*loc).unwrap())
-            .sum::<u16>();
-        binsum += u4_locs
-            .iter()
-            .map(|loc| u4.get_bit_1base(*loc).unwrap())
-            .sum::<u16>();
+        let u4 = self.peel_last_round(ct);
+        let parity = pt.parity_of_mask(pt_mask) ^ u4.parity_of_mask(u4_mask);
 
-        return 1 - binsum % 2;
+        return 1 - parity;
     }
 
     /// Compute the bias of the input linear relation over the inputs PT-CT
-    /// pairs
+    /// pairs. Both sets of locs must be non-empty and between 1 and 16, the
+    /// pairs must be non-empty, and there must be as many ciphertexts as
+    /// plaintexts; all of this is checked before any pair is visited.
     pub fn get_bias(
         &self,
         plaintexts: &[Block],
         ciphertexts: &[Block],
         pt_locs: &[u8],
         u4_locs: &[u8],
-    ) -> f64 {
+    ) -> Result<f64> {
+        let bias = self.get_bias_signed(plaintexts, ciphertexts, pt_locs, u4_locs)?;
+        return Ok(bias.abs());
+    }
+
+    /// Like `get_bias`, but keeps the direction of the correlation: the bias is
+    /// negative when the relation holds less than half the time
+    pub fn get_bias_signed(
+        &self,
+        plaintexts: &[Block],
+        ciphertexts: &[Block],
+        pt_locs: &[u8],
+        u4_locs: &[u8],
+    ) -> Result<f64> {
+        validate_pairs(plaintexts, ciphertexts)?;
+        let pt_mask = validate_locs("pt_locs", pt_locs)?;
+        let u4_mask = validate_locs("u4_locs", u4_locs)?;
         let sum = plaintexts
             .iter()
             .zip(ciphertexts.iter())
-            .map(|(pt, ct)| self.check_linear_approx(pt, ct, pt_locs, u4_locs))
-            .sum::<u16>();
+            .map(|(pt, ct)| self.check_linear_approx_mask(pt, ct, pt_mask, u4_mask) as usize)
+            .sum::<usize>();
         let prob = (sum as f64) / (plaintexts.len() as f64);
-        if prob > 0.5 {
-            return prob - 0.5;
+        return Ok(prob - 0.5);
+    }
+
+    /// The signed bias of `get_bias_signed` together with the lower and upper
+    /// bounds of its Wilson score interval at the given `confidence`, as
+    /// (bias, lower, upper). The interval is computed for the probability
+    /// that the relation holds and shifted back by 1/2.
+    #[cfg(feature = "std")]
+    pub fn get_bias_with_interval(
+        &self,
+        plaintexts: &[Block],
+        ciphertexts: &[Block],
+        pt_locs: &[u8],
+        u4_locs: &[u8],
+        confidence: f64,
+    ) -> Result<(f64, f64, f64)> {
+        if !(confidence > 0.0 && confidence < 1.0) {
+            return Err(Error::InvalidConfidence(confidence));
         }
-        return 0.5 - prob;
+        let bias = self.get_bias_signed(plaintexts, ciphertexts, pt_locs, u4_locs)?;
+        let (lower, upper) = wilson_interval(bias + 0.5, plaintexts.len(), confidence);
+        return Ok((bias, lower - 0.5, upper - 0.5));
     }
-}
 
-/// Given in the input PT-CT pairs and the relationship specified by P-locs
-/// U4-locs, return all possible K5 values, ranked by bias
-pub fn brute_force_k5(
-    plaintexts: &[Block],
-    ciphertexts: &[Block],
-    pt_locs: &[u8],
-    u4_locs: &[u8],
-    verbose: bool,
-) -> Vec<(f64, u16)> {
-    let mut rankings: Vec<(f64, u16)> = (u16::MIN..=u16::MAX)
-        .map(|round_key| {
-            if verbose {
-                println!("{round_key}");
+    /// Signed bias of every combination of plaintext mask and U4 mask, sorted
+    /// by magnitude with ties in mask order. The U4 states are peeled from the
+    /// ciphertexts once and shared by all the combinations.
+    pub fn bias_spectrum(
+        &self,
+        plaintexts: &[Block],
+        ciphertexts: &[Block],
+        pt_masks: &[u16],
+        u4_masks: &[u16],
+    ) -> Result<Vec<(u16, u16, f64)>> {
+        validate_pairs(plaintexts, ciphertexts)?;
+        let u4s = ciphertexts
+            .iter()
+            .map(|ct| self.peel_last_round(ct))
+            .collect::<Vec<Block>>();
+        let mut spectrum = vec![];
+        for pt_mask in pt_masks {
+            let pt_parities = plaintexts
+                .iter()
+                .map(|pt| pt.parity_of_mask(*pt_mask))
+                .collect::<Vec<u16>>();
+            for u4_mask in u4_masks {
+                let holds = pt_parities
+                    .iter()
+                    .zip(u4s.iter())
+                    .filter(|(pt_parity, u4)| **pt_parity == u4.parity_of_mask(*u4_mask))
+                    .count();
+                let bias = holds as f64 / plaintexts.len() as f64 - 0.5;
+                spectrum.push((*pt_mask, *u4_mask, bias));
             }
-            let cipher = HeysCipher::from_keys(&[0, 0, 0, 0, round_key]).unwrap();
-            let bias = cipher.get_bias(plaintexts, ciphertexts, pt_locs, u4_locs);
-            return (bias, round_key);
-        })
-        .collect::<Vec<(f64, u16)>>();
-    rankings.sort_by(|elem1, elem2| {
-        let (bias1, _) = elem1;
-        let (bias2, _) = elem2;
-        return bias2.partial_cmp(bias1).unwrap(); // reverse sort
-    });
-    return rankings;
+        }
+        spectrum.sort_by(|(pt_mask1, u4_mask1, bias1), (pt_mask2, u4_mask2, bias2)| {
+            return bias2
+                .abs()
+                .total_cmp(&bias1.abs())
+                .then(pt_mask1.cmp(pt_mask2))
+                .then(u4_mask1.cmp(u4_mask2));
+        });
+        return Ok(spectrum);
+    }
+
+    /// Signed bias between the plaintext bits at `pt_locs` and each single U4
+    /// bit, where U4 is peeled off with `k5` instead of the cipher's own last
+    /// round key. Entry `i` is for U4 bit loc `i + 1`, so the four entries of
+    /// S-box `n` are `4 * (n - 1)` to `4 * n - 1`.
+    pub fn single_bit_u4_spectrum(
+        &self,
+        plaintexts: &[Block],
+        ciphertexts: &[Block],
+        pt_locs: &[u8],
+        k5: u16,
+    ) -> Result<[f64; 16]> {
+        let pt_mask = validate_locs("pt_locs", pt_locs)?;
+        validate_pairs(plaintexts, ciphertexts)?;
+        let mut holds = [0usize; 16];
+        for (pt, ct) in plaintexts.iter().zip(ciphertexts.iter()) {
+            let pt_parity = pt.parity_of_mask(pt_mask);
+            let u4 = ct.mix_key(k5).substitute(self.sbox_invert());
+            for (i, count) in holds.iter_mut().enumerate() {
+                if pt_parity == (u4.value() >> (15 - i)) & 1 {
+                    *count += 1;
+                }
+            }
+        }
+        return Ok(holds.map(|count| (count as f64) / (plaintexts.len() as f64) - 0.5));
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Running bias of a linear approximation of Heys' cipher over PT-CT pairs
+/// that arrive one at a time, so that they never need to be collected
+#[derive(Debug, Clone)]
+pub struct BiasAccumulator {
+    pt_mask: u16,
+    u4_mask: u16,
+    satisfied: usize,
+    total: usize,
+}
 
-    #[test]
-    fn test_cipher_correctness() {
-        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
-        for val in 0x0000u16..=0xffff {
-            let pt = Block::new(val);
-            let ct = cipher.encrypt(&pt).unwrap();
-            assert_eq!(cipher.decrypt(&ct).unwrap(), pt);
+impl BiasAccumulator {
+    /// Accumulator for the approximation between the plaintext bits at
+    /// `pt_locs` and the U4 bits at `u4_locs`
+    pub fn new(pt_locs: &[u8], u4_locs: &[u8]) -> Result<Self> {
+        return Ok(Self {
+            pt_mask: validate_locs("pt_locs", pt_locs)?,
+            u4_mask: validate_locs("u4_locs", u4_locs)?,
+            satisfied: 0,
+            total: 0,
+        });
+    }
+
+    /// Count one pair, peeling the last round of `ct` with `k5`
+    pub fn update(&mut self, pt: &Block, ct: &Block, k5: u16) {
+        let u4 = ct.mix_key(k5).substitute(&SBOX_INVERT);
+        if pt.parity_of_mask(self.pt_mask) == u4.parity_of_mask(self.u4_mask) {
+            self.satisfied += 1;
         }
+        self.total += 1;
     }
 
-    #[test]
-    fn test_sbox() {
-        let block = Block::new(0xabcd);
-        assert_eq!(block.substitute(&SBOX).unwrap(), Block::new(0x6C59));
+    /// Number of pairs counted so far
+    pub fn total(&self) -> usize {
+        return self.total;
     }
 
-    #[test]
-    fn test_permutation() {
-        assert_eq!(
-            Block::new(0b1111000000000000)
-                .permute(&PERMUTATION)
-                .unwrap(),
-            Block::new(0b1000100010001000)
-        );
+    /// Signed bias over the pairs counted so far, as in `get_bias_signed`, or
+    /// NaN before the first pair
+    pub fn bias(&self) -> f64 {
+        return (self.satisfied as f64) / (self.total as f64) - 0.5;
+    }
+}
+
+/// Ranks every K5 candidate like `brute_force_k5` over PT-CT pairs that
+/// arrive one at a time. Only the number of pairs per value of the ciphertext
+/// bits under the active S-boxes and the plaintext parity is kept, so memory
+/// stays bounded however many pairs are fed in.
+#[derive(Debug, Clone)]
+pub struct StreamingBruteForce {
+    pt_mask: u16,
+    u4_mask: u16,
+    groups: BTreeMap<(u16, u16), usize>,
+    total: usize,
+}
+
+impl StreamingBruteForce {
+    pub fn new(pt_locs: &[u8], u4_locs: &[u8]) -> Result<Self> {
+        return Ok(Self {
+            pt_mask: validate_locs("pt_locs", pt_locs)?,
+            u4_mask: validate_locs("u4_locs", u4_locs)?,
+            groups: BTreeMap::new(),
+            total: 0,
+        });
+    }
+
+    /// Count one pair towards every candidate
+    pub fn update(&mut self, pt: &Block, ct: &Block) {
+        let active = active_sbox_mask(self.u4_mask);
+        let group = (ct.value() & active, pt.parity_of_mask(self.pt_mask));
+        *self.groups.entry(group).or_insert(0) += 1;
+        self.total += 1;
+    }
+
+    /// Number of pairs counted so far
+    pub fn total(&self) -> usize {
+        return self.total;
+    }
+
+    /// Every K5 candidate with its signed bias over the pairs so far, in the
+    /// order of `sort_rankings`. The biases are NaN before the first pair.
+    pub fn rankings(&self) -> Vec<(f64, u16)> {
+        let groups = self
+            .groups
+            .iter()
+            .map(|(group, count)| (*group, *count))
+            .collect::<Vec<((u16, u16), usize)>>();
+        let mut counts = vec![0; 1 << 16];
+        for (holds, partial_key) in count_groups(&groups, self.u4_mask, &SBOX_INVERT) {
+            counts[partial_key as usize] = holds;
+        }
+        let active = active_sbox_mask(self.u4_mask);
+        let mut rankings = (u16::MIN..=u16::MAX)
+            .map(|round_key| {
+                let holds = counts[(round_key & active) as usize];
+                return ((holds as f64) / (self.total as f64) - 0.5, round_key);
+            })
+            .collect::<Vec<(f64, u16)>>();
+        sort_rankings(&mut rankings);
+        return rankings;
+    }
+}
+
+/// Correlation `c = 2 * bias`
+pub fn bias_to_correlation(bias: f64) -> f64 {
+    return 2.0 * bias;
+}
+
+/// Linear probability `LP = c^2`
+pub fn correlation_to_linear_probability(c: f64) -> f64 {
+    return c * c;
+}
+
+/// Mask selecting the input big-endian, 1-based bit locs. A repeated loc
+/// cancels itself out, just as it would in the sum of the bits.
+pub fn locs_to_mask(locs: &[u8]) -> Result<u16> {
+    let mut mask = 0;
+    for &loc in locs {
+        if !(1..=16).contains(&loc) {
+            return Err(Error::LocOutOfRange {
+                loc: loc as usize,
+                first: 1,
+                last: 16,
+            });
+        }
+        mask ^= 1u16 << (16 - loc);
+    }
+    return Ok(mask);
+}
+
+/// Mask of the locs, or an error naming `name` and the first bad loc if the
+/// locs are empty or out of range
+fn validate_locs(name: &'static str, locs: &[u8]) -> Result<u16> {
+    if locs.is_empty() {
+        return Err(Error::EmptyLocs { name });
+    }
+    if let Some(loc) = locs.iter().find(|loc| !(1..=16).contains(*loc)) {
+        return Err(Error::InvalidLocs { name, loc: *loc });
+    }
+    return locs_to_mask(locs);
+}
+
+/// Check that every plaintext has a ciphertext and that there is at least one
+/// pair, reporting both lengths on a mismatch rather than letting `zip`
+/// silently drop the extra blocks
+pub(crate) fn validate_pairs(plaintexts: &[Block], ciphertexts: &[Block]) -> Result<()> {
+    if plaintexts.len() != ciphertexts.len() {
+        return Err(Error::LengthMismatch {
+            plaintexts: plaintexts.len(),
+            ciphertexts: ciphertexts.len(),
+        });
+    }
+    if plaintexts.is_empty() {
+        return Err(Error::NoPairs);
+    }
+    return Ok(());
+}
+
+/// All 65536 blocks in ascending order
+pub fn full_plaintext_space() -> Vec<Block> {
+    return (u16::MIN..=u16::MAX)
+        .map(Block::new)
+        .collect::<Vec<Block>>();
+}
+
+/// `count` chosen plaintexts whose bits outside `active_mask` are zero and
+/// whose bits inside it are drawn from a `StdRng` seeded with `seed`, e.g. to
+/// feed inputs only to the S-boxes under the mask
+pub fn generate_chosen_plaintexts(active_mask: u16, count: usize, seed: u64) -> Vec<Block> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    return (0..count)
+        .map(|_| Block::new(rng.gen::<u16>() & active_mask))
+        .collect::<Vec<Block>>();
+}
+
+/// Observed signed bias of the approximation under the cipher's own keys at
+/// each of `sample_sizes`, over the first that many of one stream of random
+/// plaintexts drawn from a `StdRng` seeded with `seed`. Since the samples are
+/// nested, the sequence shows how the observed bias settles as N grows.
+pub fn bias_convergence(
+    cipher: &HeysCipher,
+    pt_locs: &[u8],
+    u4_locs: &[u8],
+    sample_sizes: &[usize],
+    seed: u64,
+) -> Result<Vec<(usize, f64)>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let max_size = sample_sizes.iter().copied().max().unwrap_or(0);
+    let plaintexts = (0..max_size)
+        .map(|_| Block::new(rng.gen()))
+        .collect::<Vec<Block>>();
+    let ciphertexts = cipher.encrypt_blocks(&plaintexts)?;
+    return sample_sizes
+        .iter()
+        .map(|size| {
+            let bias = cipher.get_bias_signed(
+                &plaintexts[..*size],
+                &ciphertexts[..*size],
+                pt_locs,
+                u4_locs,
+            )?;
+            return Ok((*size, bias));
+        })
+        .collect::<Result<Vec<(usize, f64)>>>();
+}
+
+/// Given in the input PT-CT pairs and the relationship specified by P-locs
+/// U4-locs, return all possible K5 values with their signed bias, ranked by the
+/// bias magnitude
+///
+/// `other_keys` (K1 to K4) are never used: like `check_linear_approx`, peeling
+/// off the last round only needs the candidate K5 and `SBOX_INVERT`, so the
+/// other keys do not change the rankings and can be left as zero when unknown.
+///
+/// Only the K5 nibbles under the S-boxes touched by `u4_locs` are counted, so
+/// an approximation through two S-boxes costs 256 partial keys rather than
+/// 65536, and candidates sharing those nibbles get bit-identical biases.
+///
+/// With the `parallel` feature the candidate keys are evaluated across threads;
+/// the rankings are identical to the serial version.
+pub fn brute_force_k5(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    pt_locs: &[u8],
+    u4_locs: &[u8],
+    other_keys: [u16; 4],
+) -> Result<Vec<(f64, u16)>> {
+    return brute_force_k5_with_progress(
+        plaintexts,
+        ciphertexts,
+        pt_locs,
+        u4_locs,
+        other_keys,
+        None,
+    );
+}
+
+/// Like `brute_force_k5`, but `progress` is called with each candidate key and
+/// its signed bias as soon as the candidate has been evaluated. With the
+/// `parallel` feature it is called from several threads and not in key order,
+/// hence the `Sync` bound.
+pub fn brute_force_k5_with_progress(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    pt_locs: &[u8],
+    u4_locs: &[u8],
+    _other_keys: [u16; 4],
+    progress: Option<&(dyn Fn(u16, f64) + Sync)>,
+) -> Result<Vec<(f64, u16)>> {
+    let candidates = (u16::MIN..=u16::MAX).collect::<Vec<u16>>();
+    return rank_k5_candidates(
+        &candidates,
+        plaintexts,
+        ciphertexts,
+        pt_locs,
+        u4_locs,
+        progress,
+    );
+}
+
+/// Like `brute_force_k5`, but ranks over `sample_size` PT-CT pairs chosen
+/// uniformly at random without replacement, so that the sample does not
+/// depend on how the pairs are ordered. The same `seed` picks the same pairs.
+pub fn brute_force_k5_sampled(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    pt_locs: &[u8],
+    u4_locs: &[u8],
+    sample_size: usize,
+    seed: u64,
+) -> Result<Vec<(f64, u16)>> {
+    validate_pairs(plaintexts, ciphertexts)?;
+    if sample_size > plaintexts.len() {
+        return Err(Error::SampleTooLarge {
+            sample_size,
+            pairs: plaintexts.len(),
+        });
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (sampled_pts, sampled_cts): (Vec<Block>, Vec<Block>) =
+        rand::seq::index::sample(&mut rng, plaintexts.len(), sample_size)
+            .into_iter()
+            .map(|i| (plaintexts[i], ciphertexts[i]))
+            .unzip();
+    return brute_force_k5(&sampled_pts, &sampled_cts, pt_locs, u4_locs, [0; 4]);
+}
+
+/// `brute_force_k5_partial` with the approximation read off `trail`, over
+/// only the K5 nibbles under the S-boxes its U mask activates
+pub fn brute_force_from_trail(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    trail: &MultiRoundTrail,
+) -> Result<Vec<(f64, u16)>> {
+    return brute_force_k5_partial(
+        plaintexts,
+        ciphertexts,
+        &trail.pt_locs(),
+        &trail.u4_locs(),
+        active_sbox_mask(trail.u_mask),
+    );
+}
+
+/// How well one linear approximation recovered a known K5, as reported by
+/// `compare_approximations`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApproxResult {
+    /// The approximation as (PT locs, U4 locs)
+    pub approx: (Vec<u8>, Vec<u8>),
+    /// Signed bias of the approximation under the true K5
+    pub bias: f64,
+    /// 1-based position of the true K5 in the rankings, so 1 means the
+    /// approximation recovered it. Only the K5 nibbles under the active
+    /// S-boxes are ranked, so candidates differing elsewhere do not count.
+    pub rank: usize,
+}
+
+/// Rank K5 with each of the approximations over the same PT-CT pairs and
+/// report where `true_key` lands, in the order of `approxes`. The lower the
+/// rank and the larger the bias, the stronger the approximation is on this
+/// data.
+pub fn compare_approximations(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    true_key: u16,
+    approxes: &[(Vec<u8>, Vec<u8>)],
+) -> Result<Vec<ApproxResult>> {
+    return approxes
+        .iter()
+        .map(|(pt_locs, u4_locs)| {
+            let active = active_sbox_mask(validate_locs("u4_locs", u4_locs)?);
+            let rankings =
+                brute_force_k5_partial(plaintexts, ciphertexts, pt_locs, u4_locs, active)?;
+            let (position, (bias, _)) = rankings
+                .iter()
+                .enumerate()
+                .find(|(_, (_, round_key))| *round_key == true_key & active)
+                .expect("every subset of the active mask is ranked");
+            return Ok(ApproxResult {
+                approx: (pt_locs.clone(), u4_locs.clone()),
+                bias: *bias,
+                rank: position + 1,
+            });
+        })
+        .collect::<Result<Vec<ApproxResult>>>();
+}
+
+/// Like `brute_force_k5`, but only the K5 bits set in `active_sbox_mask` are
+/// enumerated and the rest are left zero. For example, an approximation whose
+/// U4 bits all fall in the 2nd and 4th S-boxes only needs the mask `0x0f0f`,
+/// which is 256 candidates instead of 65536.
+pub fn brute_force_k5_partial(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    pt_locs: &[u8],
+    u4_locs: &[u8],
+    active_sbox_mask: u16,
+) -> Result<Vec<(f64, u16)>> {
+    // Enumerate every subset of the mask's bits in ascending order
+    let mut candidates: Vec<u16> = vec![];
+    let mut round_key: u16 = 0;
+    loop {
+        candidates.push(round_key);
+        if round_key == active_sbox_mask {
+            break;
+        }
+        round_key = round_key.wrapping_sub(active_sbox_mask) & active_sbox_mask;
+    }
+    return rank_k5_candidates(&candidates, plaintexts, ciphertexts, pt_locs, u4_locs, None);
+}
+
+/// Sort (bias, key) candidates by |bias| descending with NaN biases last. Ties
+/// are broken by ascending key so that the order does not depend on how the
+/// candidates were evaluated.
+pub fn sort_rankings(rankings: &mut [(f64, u16)]) {
+    rankings.sort_by(cmp_rankings);
+}
+
+/// The order of `sort_rankings`, which is total since no two entries share a key
+fn cmp_rankings((bias1, key1): &(f64, u16), (bias2, key2): &(f64, u16)) -> Ordering {
+    return bias1
+        .is_nan()
+        .cmp(&bias2.is_nan())
+        .then(bias2.abs().total_cmp(&bias1.abs()))
+        .then(key1.cmp(key2));
+}
+
+/// Rank every K5 candidate by how often `event(pt, ct, k5)` holds over the
+/// PT-CT pairs, for relations other than the parity check of
+/// `check_linear_approx`. Each candidate gets the signed bias `freq - 0.5`,
+/// ranked by magnitude as in `brute_force_k5`, which is the special case
+/// where the event is that the linear approximation holds.
+///
+/// Unlike `brute_force_k5` nothing is known about which key bits the event
+/// depends on, so it is called for every candidate and every pair.
+pub fn brute_force_k5_with_event<F>(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    event: F,
+) -> Result<Vec<(f64, u16)>>
+where
+    F: Fn(&Block, &Block, u16) -> bool + Sync,
+{
+    validate_pairs(plaintexts, ciphertexts)?;
+    let rank = |round_key: u16| -> (f64, u16) {
+        let holds = plaintexts
+            .iter()
+            .zip(ciphertexts.iter())
+            .filter(|(pt, ct)| event(pt, ct, round_key))
+            .count();
+        return ((holds as f64) / (plaintexts.len() as f64) - 0.5, round_key);
+    };
+    #[cfg(feature = "parallel")]
+    let mut rankings = (u16::MIN..=u16::MAX)
+        .into_par_iter()
+        .map(rank)
+        .collect::<Vec<(f64, u16)>>();
+    #[cfg(not(feature = "parallel"))]
+    let mut rankings = (u16::MIN..=u16::MAX).map(rank).collect::<Vec<(f64, u16)>>();
+    sort_rankings(&mut rankings);
+    return Ok(rankings);
+}
+
+/// The entries of `brute_force_k5` whose bias magnitude is at least
+/// `min_bias`, in the same order. Only these entries are sorted.
+pub fn brute_force_k5_above(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    pt_locs: &[u8],
+    u4_locs: &[u8],
+    min_bias: f64,
+) -> Result<Vec<(f64, u16)>> {
+    let (active, counts) = count_k5_candidates(plaintexts, ciphertexts, pt_locs, u4_locs)?;
+    let mut rankings = (u16::MIN..=u16::MAX)
+        .map(|round_key| {
+            let holds = counts[(round_key & active) as usize];
+            return ((holds as f64) / (plaintexts.len() as f64) - 0.5, round_key);
+        })
+        .filter(|(bias, _)| bias.abs() >= min_bias)
+        .collect::<Vec<(f64, u16)>>();
+    sort_rankings(&mut rankings);
+    return Ok(rankings);
+}
+
+/// The first entry `brute_force_k5` would rank, found in a single pass over
+/// the candidates without collecting and sorting all of them
+pub fn best_k5(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    pt_locs: &[u8],
+    u4_locs: &[u8],
+) -> Result<(f64, u16)> {
+    let (active, counts) = count_k5_candidates(plaintexts, ciphertexts, pt_locs, u4_locs)?;
+    let rank = |round_key: u16| -> (f64, u16) {
+        let holds = counts[(round_key & active) as usize];
+        return ((holds as f64) / (plaintexts.len() as f64) - 0.5, round_key);
+    };
+    #[cfg(feature = "parallel")]
+    let best = (u16::MIN..=u16::MAX)
+        .into_par_iter()
+        .map(rank)
+        .min_by(cmp_rankings);
+    #[cfg(not(feature = "parallel"))]
+    let best = (u16::MIN..=u16::MAX).map(rank).min_by(cmp_rankings);
+    return Ok(best.expect("there is at least one candidate key"));
+}
+
+/// For every K5 value in ascending order, the number of PT-CT pairs that
+/// satisfy the approximation out of the total, as `(key, satisfied, total)`.
+/// The bias `brute_force_k5` reports is `satisfied / total - 0.5`.
+pub fn brute_force_k5_counts(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    pt_locs: &[u8],
+    u4_locs: &[u8],
+) -> Result<Vec<(u16, usize, usize)>> {
+    let (active, counts) = count_k5_candidates(plaintexts, ciphertexts, pt_locs, u4_locs)?;
+    return Ok((u16::MIN..=u16::MAX)
+        .map(|round_key| {
+            let holds = counts[(round_key & active) as usize];
+            return (round_key, holds, plaintexts.len());
+        })
+        .collect::<Vec<(u16, usize, usize)>>());
+}
+
+/// Count the pairs satisfying the approximation for every value of the K5
+/// bits under the S-boxes touched by the U4 locs. Returns those bits as a mask
+/// together with the counts, indexed by `round_key & mask`.
+fn count_k5_candidates(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    pt_locs: &[u8],
+    u4_locs: &[u8],
+) -> Result<(u16, Vec<usize>)> {
+    validate_pairs(plaintexts, ciphertexts)?;
+    let pt_mask = validate_locs("pt_locs", pt_locs)?;
+    let u4_mask = validate_locs("u4_locs", u4_locs)?;
+    let mut counts = vec![0; 1 << 16];
+    for (holds, partial_key) in
+        partial_key_counts(plaintexts, ciphertexts, pt_mask, u4_mask, &SBOX_INVERT)
+    {
+        counts[partial_key as usize] = holds;
+    }
+    return Ok((active_sbox_mask(u4_mask), counts));
+}
+
+/// Try each candidate as K5 and rank them by the bias of the approximation
+///
+/// Only the K5 bits under the S-boxes touched by the U4 locs affect the bias,
+/// so the biases are computed once per value of those bits and every candidate
+/// looks up its own. Each candidate's bias is logged at debug level and the
+/// best candidate at info level.
+fn rank_k5_candidates(
+    candidates: &[u16],
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    pt_locs: &[u8],
+    u4_locs: &[u8],
+    progress: Option<&(dyn Fn(u16, f64) + Sync)>,
+) -> Result<Vec<(f64, u16)>> {
+    let (active, counts) = count_k5_candidates(plaintexts, ciphertexts, pt_locs, u4_locs)?;
+    let rank = |round_key: u16| -> (f64, u16) {
+        let holds = counts[(round_key & active) as usize];
+        let bias = (holds as f64) / (plaintexts.len() as f64) - 0.5;
+        debug!("K5 candidate 0x{round_key:04x}: bias {bias:.6}");
+        if let Some(progress) = progress {
+            progress(round_key, bias);
+        }
+        return (bias, round_key);
+    };
+    #[cfg(feature = "parallel")]
+    let mut rankings = candidates
+        .par_iter()
+        .map(|round_key| rank(*round_key))
+        .collect::<Vec<(f64, u16)>>();
+    #[cfg(not(feature = "parallel"))]
+    let mut rankings = candidates
+        .iter()
+        .map(|round_key| rank(*round_key))
+        .collect::<Vec<(f64, u16)>>();
+    sort_rankings(&mut rankings);
+    if let Some((bias, round_key)) = rankings.first() {
+        info!(
+            "Best of {} K5 candidates over {} PT-CT pairs: 0x{round_key:04x} with bias {bias:.6}",
+            rankings.len(),
+            plaintexts.len()
+        );
+    }
+    return Ok(rankings);
+}
+
+/// Substitute a single nibble.
+///
+/// Panics if `nibble` is 16 or more, or if the S-box maps it to 16 or more,
+/// which would otherwise corrupt the neighbouring nibble.
+pub fn sbox_apply(sbox: &[u16; 16], nibble: u16) -> u16 {
+    assert!(nibble < 16, "nibble must be below 16 but got {nibble}");
+    let output = sbox[nibble as usize];
+    assert!(
+        output < 16,
+        "S-box maps {nibble} to {output}, which is not below 16"
+    );
+    return output;
+}
+
+/// The input nibble that the S-box maps to `output`, or an error if there is
+/// none, which cannot happen for a bijection
+pub fn sbox_invert(sbox: &[u16; 16], output: u16) -> Result<u16> {
+    return match sbox.iter().position(|entry| *entry == output) {
+        Some(input) => Ok(input as u16),
+        None => Err(Error::SboxNoPreimage { output }),
+    };
+}
+
+/// Number of S-boxes touched by `mask`, i.e. its non-zero nibbles
+pub fn count_active_sboxes(mask: u16) -> u32 {
+    return active_sbox_indices(mask).len() as u32;
+}
+
+/// Indices of the S-boxes touched by `mask` in ascending order, counting from
+/// 0 at the most significant nibble, so index `i` is Heys' S-box `i + 1`
+pub fn active_sbox_indices(mask: u16) -> Vec<usize> {
+    return (0..4)
+        .filter(|sbox| (mask >> (12 - 4 * sbox)) & 0xf != 0)
+        .collect::<Vec<usize>>();
+}
+
+/// Mask of the whole S-boxes touched by `mask`, e.g. 0x0f0f for the U4 mask
+/// 0x0505, which is the `active_sbox_mask` of `brute_force_k5_partial`
+pub fn active_sbox_mask(mask: u16) -> u16 {
+    return (0..16)
+        .step_by(4)
+        .filter(|shift| (mask >> shift) & 0xf != 0)
+        .map(|shift| 0xf << shift)
+        .sum();
+}
+
+/// Signed bias of the approximation for every last round key on the S-boxes
+/// activated by `u4_mask`, with the other key bits left zero, in ascending
+/// key order
+pub(crate) fn partial_key_biases(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    pt_mask: u16,
+    u4_mask: u16,
+    sbox_invert: &[u16; 16],
+) -> Vec<(f64, u16)> {
+    return partial_key_counts(plaintexts, ciphertexts, pt_mask, u4_mask, sbox_invert)
+        .into_iter()
+        .map(|(holds, partial_key)| {
+            let bias = (holds as f64) / (plaintexts.len() as f64) - 0.5;
+            return (bias, partial_key);
+        })
+        .collect::<Vec<(f64, u16)>>();
+}
+
+/// Like `partial_key_biases`, but with the number of pairs satisfying the
+/// approximation instead of the bias. Pairs are first grouped by the
+/// ciphertext bits under the active S-boxes and the plaintext parity, so each
+/// key only visits the distinct groups instead of every pair.
+fn partial_key_counts(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    pt_mask: u16,
+    u4_mask: u16,
+    sbox_invert: &[u16; 16],
+) -> Vec<(usize, u16)> {
+    let active = active_sbox_mask(u4_mask);
+    let mut counts: BTreeMap<(u16, u16), usize> = BTreeMap::new();
+    for (pt, ct) in plaintexts.iter().zip(ciphertexts.iter()) {
+        let group = (ct.value() & active, pt.parity_of_mask(pt_mask));
+        *counts.entry(group).or_insert(0) += 1;
+    }
+    let groups = counts.into_iter().collect::<Vec<((u16, u16), usize)>>();
+    return count_groups(&groups, u4_mask, sbox_invert);
+}
+
+/// The counting half of `partial_key_counts`, over pairs already grouped by
+/// (ciphertext bits under the active S-boxes, plaintext parity)
+fn count_groups(
+    groups: &[((u16, u16), usize)],
+    u4_mask: u16,
+    sbox_invert: &[u16; 16],
+) -> Vec<(usize, u16)> {
+    let active = active_sbox_mask(u4_mask);
+    // Every subset of the active bits in ascending order
+    let mut partial_keys: Vec<u16> = vec![];
+    let mut partial_key: u16 = 0;
+    loop {
+        partial_keys.push(partial_key);
+        if partial_key == active {
+            break;
+        }
+        partial_key = partial_key.wrapping_sub(active) & active;
+    }
+    let count = |partial_key: u16| -> (usize, u16) {
+        let holds = groups
+            .iter()
+            .filter(|((ct_bits, pt_parity), _)| {
+                let u4 = Block::new(ct_bits ^ partial_key).substitute(sbox_invert);
+                return u4.parity_of_mask(u4_mask) == *pt_parity;
+            })
+            .map(|(_, count)| count)
+            .sum::<usize>();
+        return (holds, partial_key);
+    };
+    #[cfg(feature = "parallel")]
+    return partial_keys
+        .par_iter()
+        .map(|partial_key| count(*partial_key))
+        .collect::<Vec<(usize, u16)>>();
+    #[cfg(not(feature = "parallel"))]
+    return partial_keys
+        .iter()
+        .map(|partial_key| count(*partial_key))
+        .collect::<Vec<(usize, u16)>>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const SBOX_PAIRS: [(u16, u16); 16] = [
+        (0x0, 0xE),
+        (0x1, 0x4),
+        (0x2, 0xD),
+        (0x3, 0x1),
+        (0x4, 0x2),
+        (0x5, 0xF),
+        (0x6, 0xB),
+        (0x7, 0x8),
+        (0x8, 0x3),
+        (0x9, 0xA),
+        (0xA, 0x6),
+        (0xB, 0xC),
+        (0xC, 0x5),
+        (0xD, 0x9),
+        (0xE, 0x0),
+        (0xF, 0x7),
+    ];
+
+    const PERMUTATION_PAIRS: [(u16, u16); 16] = [
+        (0x8000, 0x8000),
+        (0x4000, 0x0800),
+        (0x2000, 0x0080),
+        (0x1000, 0x0008),
+        (0x0800, 0x4000),
+        (0x0400, 0x0400),
+        (0x0200, 0x0040),
+        (0x0100, 0x0004),
+        (0x0080, 0x2000),
+        (0x0040, 0x0200),
+        (0x0020, 0x0020),
+        (0x0010, 0x0002),
+        (0x0008, 0x1000),
+        (0x0004, 0x0100),
+        (0x0002, 0x0010),
+        (0x0001, 0x0001),
+    ];
+
+    fn lookup(kvpairs: &[(u16, u16)], val: u16) -> u16 {
+        for (from, to) in kvpairs {
+            if *from == val {
+                return *to;
+            }
+        }
+        panic!("Lookup failed");
+    }
+
+    /// Encryption using the original linear-scan pair tables
+    fn encrypt_with_pairs(round_keys: &[u16; 5], pt: u16) -> u16 {
+        let mut state = pt;
+        for (round, round_key) in round_keys.iter().take(4).enumerate() {
+            state ^= round_key;
+            state = (0..4)
+                .map(|nibble| lookup(&SBOX_PAIRS, (state >> (4 * nibble)) % 16) << (4 * nibble))
+                .sum();
+            if round < 3 {
+                state = (0..16)
+                    .map(|shift| 1u16 << shift)
+                    .filter(|mask| state & mask != 0)
+                    .map(|mask| lookup(&PERMUTATION_PAIRS, mask))
+                    .sum();
+            }
+        }
+        return state ^ round_keys[4];
+    }
+
+    #[test]
+    fn test_tables_match_pairs() {
+        let round_keys = [0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e];
+        let cipher = HeysCipher::from_keys(&round_keys).unwrap();
+        for val in 0x0000u16..=0xffff {
+            let ct = cipher.encrypt(&Block::new(val)).unwrap();
+            assert_eq!(ct, Block::new(encrypt_with_pairs(&round_keys, val)));
+        }
+    }
+
+    #[test]
+    fn test_cipher_correctness() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        for val in 0x0000u16..=0xffff {
+            let pt = Block::new(val);
+            let ct = cipher.encrypt(&pt).unwrap();
+            assert_eq!(cipher.decrypt(&ct).unwrap(), pt);
+        }
+    }
+
+    #[test]
+    fn test_reduced_round_correctness() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4]).unwrap();
+        for val in 0x0000u16..=0xffff {
+            let pt = Block::new(val);
+            let ct = cipher.encrypt(&pt).unwrap();
+            assert_eq!(cipher.decrypt(&ct).unwrap(), pt);
+        }
+        assert!(HeysCipher::from_keys(&[1]).is_err());
+        assert!(HeysCipher::from_keys(&[]).is_err());
+    }
+
+    #[test]
+    fn test_with_components() {
+        let cipher = HeysCipher::with_components(&[1, 2, 3, 4, 5], &SBOX, &PERMUTATION).unwrap();
+        assert_eq!(*cipher.sbox_invert(), SBOX_INVERT);
+        assert_eq!(*cipher.permutation_invert(), PERMUTATION_INVERT);
+
+        // PRESENT's S-box with a rotate-by-one permutation
+        let present_sbox = [
+            0xC, 0x5, 0x6, 0xB, 0x9, 0x0, 0xA, 0xD, 0x3, 0xE, 0xF, 0x8, 0x4, 0x7, 0x1, 0x2,
+        ];
+        let rotation = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 0];
+        let cipher =
+            HeysCipher::with_components(&[1, 2, 3, 4, 5], &present_sbox, &rotation).unwrap();
+        for val in 0x0000u16..=0xffff {
+            let pt = Block::new(val);
+            let ct = cipher.encrypt(&pt).unwrap();
+            assert_eq!(cipher.decrypt(&ct).unwrap(), pt);
+        }
+
+        let mut bad_sbox = SBOX;
+        bad_sbox[0] = bad_sbox[1];
+        assert!(HeysCipher::with_components(&[1, 2], &bad_sbox, &PERMUTATION).is_err());
+        let mut bad_permutation = PERMUTATION;
+        bad_permutation[0] = 16;
+        assert!(HeysCipher::with_components(&[1, 2], &SBOX, &bad_permutation).is_err());
+        bad_permutation[0] = bad_permutation[1];
+        assert!(HeysCipher::with_components(&[1, 2], &SBOX, &bad_permutation).is_err());
+    }
+
+    #[test]
+    fn test_invalid_locs() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        let pt = Block::new(0x1234);
+        let ct = cipher.encrypt(&pt).unwrap();
+        assert!(cipher
+            .check_linear_approx(&pt, &ct, &[5, 7, 8], &[6, 8])
+            .is_ok());
+        assert!(cipher.check_linear_approx(&pt, &ct, &[0], &[6]).is_err());
+        assert!(cipher.check_linear_approx(&pt, &ct, &[5], &[17]).is_err());
+        assert!(cipher.get_bias(&[pt], &[ct], &[0], &[6]).is_err());
+        assert!(cipher.get_bias(&[pt], &[ct], &[5], &[17]).is_err());
+        assert!(brute_force_k5(&[pt], &[ct], &[5], &[17], [0; 4]).is_err());
+    }
+
+    #[test]
+    fn test_get_bias_validates_locs() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        let pt = Block::new(0x1234);
+        let ct = cipher.encrypt(&pt).unwrap();
+        let err = cipher.get_bias(&[pt], &[ct], &[5, 0], &[6]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "pt_locs contains 0, but locs must be between 1 and 16"
+        );
+        let err = cipher.get_bias(&[pt], &[ct], &[5], &[6, 17]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "u4_locs contains 17, but locs must be between 1 and 16"
+        );
+        let err = cipher.get_bias(&[pt], &[ct], &[], &[6]).unwrap_err();
+        assert_eq!(err.to_string(), "pt_locs must not be empty");
+        let err = cipher.get_bias_signed(&[pt], &[ct], &[5], &[]).unwrap_err();
+        assert_eq!(err.to_string(), "u4_locs must not be empty");
+    }
+
+    #[test]
+    fn test_error_variants() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        let pt = Block::new(0x1234);
+        let ct = cipher.encrypt(&pt).unwrap();
+        assert_eq!(
+            cipher.get_bias(&[pt], &[ct], &[], &[6]).unwrap_err(),
+            Error::EmptyLocs { name: "pt_locs" }
+        );
+        assert_eq!(
+            cipher.get_bias(&[pt, pt], &[ct], &[5], &[6]).unwrap_err(),
+            Error::LengthMismatch {
+                plaintexts: 2,
+                ciphertexts: 1
+            }
+        );
+        assert_eq!(
+            cipher.get_bias(&[], &[], &[5], &[6]).unwrap_err(),
+            Error::NoPairs
+        );
+        assert_eq!(
+            cipher
+                .check_linear_approx(&pt, &ct, &[5], &[17])
+                .unwrap_err(),
+            Error::LocOutOfRange {
+                loc: 17,
+                first: 1,
+                last: 16
+            }
+        );
+        assert_eq!(
+            HeysCipher::from_keys(&[1]).unwrap_err(),
+            Error::TooFewRoundKeys { keys: 1 }
+        );
+        assert_eq!(
+            Block::<8>::from_word(0x100).unwrap_err(),
+            Error::WordTooWide {
+                word: 0x100,
+                bits: 8
+            }
+        );
+        assert!(matches!(Block::from_hexstr("1a2"), Err(Error::Parse(_))));
+        assert_eq!(
+            pt.with_bit_1base(1, 2).unwrap_err(),
+            Error::InvalidBitValue(2)
+        );
+    }
+
+    #[test]
+    fn test_brute_force_k5_empty_locs() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        let plaintexts = vec![Block::new(0x1234)];
+        let ciphertexts = cipher.encrypt_blocks(&plaintexts).unwrap();
+        let err = brute_force_k5(&plaintexts, &ciphertexts, &[], &[6], [0; 4]).unwrap_err();
+        assert_eq!(err.to_string(), "pt_locs must not be empty");
+        let err = brute_force_k5(&plaintexts, &ciphertexts, &[5], &[], [0; 4]).unwrap_err();
+        assert_eq!(err.to_string(), "u4_locs must not be empty");
+        assert!(best_k5(&plaintexts, &ciphertexts, &[], &[6]).is_err());
+        assert!(brute_force_k5_counts(&plaintexts, &ciphertexts, &[5], &[]).is_err());
+        assert!(brute_force_k5_above(&plaintexts, &ciphertexts, &[], &[6], 0.0).is_err());
+        let err = brute_force_k5(&plaintexts, &ciphertexts, &[5], &[6, 17], [0; 4]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "u4_locs contains 17, but locs must be between 1 and 16"
+        );
+    }
+
+    #[test]
+    fn test_bias_empty_input() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        assert!(cipher.get_bias(&[], &[], &[5, 7, 8], &[6, 8]).is_err());
+        assert!(brute_force_k5(&[], &[], &[5, 7, 8], &[6, 8], [0; 4]).is_err());
+    }
+
+    #[test]
+    fn test_bias_mismatched_lengths() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        let pt = Block::new(1);
+        let ct = cipher.encrypt(&pt).unwrap();
+        let err = cipher
+            .get_bias(&[pt, Block::new(2)], &[ct], &[5, 7, 8], &[6, 8])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "2 plaintexts but 1 ciphertexts");
+    }
+
+    #[test]
+    fn test_mismatched_lengths_everywhere() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        let plaintexts = [0x0001, 0x0002, 0x0003].map(Block::new);
+        let ciphertexts = cipher.encrypt_blocks(&plaintexts[..2]).unwrap();
+        let (pts, cts) = (&plaintexts[..], &ciphertexts[..]);
+        let (pt_locs, u4_locs) = (&[5, 7, 8][..], &[6, 8, 14, 16][..]);
+        let errs = [
+            cipher.get_bias(pts, cts, pt_locs, u4_locs).unwrap_err(),
+            cipher
+                .get_correlation(pts, cts, pt_locs, u4_locs)
+                .unwrap_err(),
+            cipher
+                .bias_spectrum(pts, cts, &[0x0b00], &[0x0505])
+                .unwrap_err(),
+            brute_force_k5(pts, cts, pt_locs, u4_locs, [0; 4]).unwrap_err(),
+            brute_force_k5_sampled(pts, cts, pt_locs, u4_locs, 2, 0).unwrap_err(),
+            brute_force_k5_partial(pts, cts, pt_locs, u4_locs, 0x0f0f).unwrap_err(),
+            brute_force_k5_above(pts, cts, pt_locs, u4_locs, 0.0).unwrap_err(),
+            brute_force_k5_counts(pts, cts, pt_locs, u4_locs).unwrap_err(),
+            best_k5(pts, cts, pt_locs, u4_locs).unwrap_err(),
+            brute_force_k5_with_event(pts, cts, |_, _, _| true).unwrap_err(),
+        ];
+        for err in errs {
+            assert_eq!(err.to_string(), "3 plaintexts but 2 ciphertexts");
+        }
+        #[cfg(feature = "std")]
+        assert_eq!(
+            cipher
+                .get_bias_with_interval(pts, cts, pt_locs, u4_locs, 0.95)
+                .unwrap_err()
+                .to_string(),
+            "3 plaintexts but 2 ciphertexts"
+        );
+    }
+
+    #[test]
+    fn test_binstr_round_trip() {
+        assert_eq!(Block::new(0).to_binstr(), "0000000000000000");
+        assert_eq!(Block::new(0xffff).to_binstr(), "1111111111111111");
+        assert_eq!(Block::new(0x0706).to_binstr(), "0000011100000110");
+        for val in [0x0000u16, 0x0001, 0x8000, 0x1234, 0xabcd, 0xfffe, 0xffff] {
+            let block = Block::new(val);
+            assert_eq!(Block::from_binstr(&block.to_binstr()).unwrap(), block);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_decrypt_inverts_encrypt(round_keys in prop::array::uniform5(any::<u16>()), pt in any::<u16>()) {
+            let cipher = HeysCipher::from_keys(&round_keys).unwrap();
+            let pt = Block::new(pt);
+            prop_assert_eq!(cipher.decrypt(&cipher.encrypt(&pt).unwrap()).unwrap(), pt);
+        }
+
+        #[test]
+        fn prop_substitute_round_trip(val in any::<u16>()) {
+            let block = Block::new(val);
+            prop_assert_eq!(block.substitute(&SBOX).substitute(&SBOX_INVERT), block);
+        }
+
+        #[test]
+        fn prop_permute_round_trip(val in any::<u16>()) {
+            let block = Block::new(val);
+            let permuted = block.permute(&PERMUTATION).unwrap();
+            prop_assert_eq!(permuted.permute(&PERMUTATION_INVERT).unwrap(), block);
+        }
+    }
+
+    #[test]
+    fn test_locs_to_mask() {
+        assert_eq!(locs_to_mask(&[5, 7, 8]).unwrap(), 0x0b00);
+        assert_eq!(locs_to_mask(&[6, 8, 14, 16]).unwrap(), 0x0505);
+        assert_eq!(locs_to_mask(&[1, 16]).unwrap(), 0x8001);
+        assert_eq!(locs_to_mask(&[3, 3]).unwrap(), 0);
+        assert!(locs_to_mask(&[0]).is_err());
+        assert!(locs_to_mask(&[17]).is_err());
+    }
+
+    #[test]
+    fn test_linear_approx_mask_agrees() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 0x0706]).unwrap();
+        for (pt, ct) in cipher.encrypt_codebook().unwrap().into_iter().step_by(13) {
+            assert_eq!(
+                cipher
+                    .check_linear_approx(&pt, &ct, &[5, 7, 8], &[6, 8, 14, 16])
+                    .unwrap(),
+                cipher.check_linear_approx_mask(&pt, &ct, 0x0b00, 0x0505),
+            );
+        }
+    }
+
+    #[test]
+    fn test_linear_approx_final_key() {
+        // A 3-round cipher has 4 round keys, and the last one is peeled off
+        let round_keys = [0x1234, 0x5678, 0x9abc, 0xdef0];
+        let cipher = HeysCipher::reduced(3, &round_keys).unwrap();
+        let final_key = cipher.round_keys()[cipher.num_rounds()];
+        assert_eq!(final_key, 0xdef0);
+        let check_with_key = |pt: &Block, ct: &Block, key: u16| {
+            let u4 = ct.mix_key(key).substitute(&SBOX_INVERT);
+            return 1 - (pt.parity_of_mask(0x0b00) ^ u4.parity_of_mask(0x0505));
+        };
+        let mut differs = false;
+        for (pt, ct) in cipher.encrypt_codebook().unwrap().into_iter().step_by(17) {
+            let check = cipher
+                .check_linear_approx(&pt, &ct, &[5, 7, 8], &[6, 8, 14, 16])
+                .unwrap();
+            assert_eq!(check, check_with_key(&pt, &ct, final_key));
+            differs |= check != check_with_key(&pt, &ct, round_keys[2]);
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn test_hamming_weight_and_parity() {
+        let block = Block::new(0b1011_0000_0000_0110);
+        assert_eq!(block.hamming_weight(), 5);
+        assert_eq!(block.parity_of_mask(0xffff), 1);
+        assert_eq!(Block::new(0xffff).parity_of_mask(0xffff), 0);
+        assert_eq!(Block::new(0xffff).hamming_weight(), 16);
+        assert_eq!(block.parity_of_mask(0xf000), 1);
+        assert_eq!(block.parity_of_mask(0x000f), 0);
+        assert_eq!(block.parity_of_mask(0x8002), 0);
+        assert_eq!(block.parity_of_mask(0), 0);
+    }
+
+    /// Perfect shuffle of an 8-bit state made of two S-boxes, and its inverse
+    const PERMUTATION_8: [usize; 8] = [0, 2, 4, 6, 1, 3, 5, 7];
+    const PERMUTATION_8_INVERT: [usize; 8] = [0, 4, 1, 5, 2, 6, 3, 7];
+
+    #[test]
+    fn test_8bit_block() {
+        let block = Block::<8>::from_word(0x12).unwrap();
+        assert_eq!(block.substitute(&SBOX).word(), 0x4d);
+        assert_eq!(block.to_string(), "0x12");
+        assert_eq!(block.to_binstr(), "00010010");
+        assert_eq!(block.get_bit_1base(4).unwrap(), 1);
+        assert_eq!(block.get_bit_1base(7).unwrap(), 1);
+        assert_eq!(block.get_bit_1base(8).unwrap(), 0);
+        assert!(block.get_bits_1base(&[9]).is_err());
+        assert_eq!(block.bits().count(), 8);
+        assert_eq!(block.mix_word(0xff12).word(), 0);
+        assert!(Block::<8>::from_word(0x100).is_err());
+        assert!(block.permute(&[0, 1, 2, 3, 4, 5, 6, 8]).is_err());
+    }
+
+    #[test]
+    fn test_8bit_spn() {
+        // Two rounds of a toy SPN with two Heys S-boxes per round
+        let keys = [0x3a, 0xc5, 0x7e];
+        let encrypt = |pt: Block<8>| -> Block<8> {
+            let state = pt.mix_word(keys[0]).substitute(&SBOX);
+            let state = state.permute(&PERMUTATION_8).unwrap().mix_word(keys[1]);
+            return state.substitute(&SBOX).mix_word(keys[2]);
+        };
+        let decrypt = |ct: Block<8>| -> Block<8> {
+            let state = ct.mix_word(keys[2]).substitute(&SBOX_INVERT);
+            let state = state
+                .mix_word(keys[1])
+                .permute(&PERMUTATION_8_INVERT)
+                .unwrap();
+            return state.substitute(&SBOX_INVERT).mix_word(keys[0]);
+        };
+        let mut ciphertexts = HashSet::new();
+        for word in 0..256 {
+            let pt = Block::<8>::from_word(word).unwrap();
+            let ct = encrypt(pt);
+            assert_eq!(decrypt(ct), pt);
+            ciphertexts.insert(ct);
+        }
+        assert_eq!(ciphertexts.len(), 256);
+    }
+
+    #[test]
+    fn test_32bit_block() {
+        let block = Block::<32>::from_word(0x0123_4567).unwrap();
+        assert_eq!(block.substitute(&SBOX).word(), 0xe4d1_2fb8);
+        assert_eq!(block.substitute(&SBOX).substitute(&SBOX_INVERT), block);
+        assert_eq!(block.get_bit_1base(32).unwrap(), 1);
+        assert_eq!(
+            Block::<32>::from_word(u32::MAX).unwrap().hamming_weight(),
+            32
+        );
+    }
+
+    #[test]
+    fn test_get_bits_1base() {
+        let block = Block::new(0x8421);
+        assert_eq!(block.get_bits_1base(&[1, 6, 11, 16]).unwrap(), vec![1; 4]);
+        assert_eq!(block.get_bits_1base(&[2, 16, 1]).unwrap(), vec![0, 1, 1]);
+        assert_eq!(block.get_bits_1base(&[]).unwrap(), Vec::<u16>::new());
+        for loc in 1..=16 {
+            assert_eq!(
+                block.get_bits_1base(&[loc]).unwrap(),
+                vec![block.get_bit_1base(loc).unwrap()]
+            );
+        }
+
+        assert!(block.get_bits_1base(&[1, 0]).is_err());
+        assert!(block.get_bits_1base(&[17, 1]).is_err());
+        assert!(Block::<8>::from_word(0x12)
+            .unwrap()
+            .get_bits_1base(&[1, 9])
+            .is_err());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "loc must be between 1 and 16")]
+    fn test_get_bit_1base_out_of_range() {
+        let _ = Block::new(0x8421).get_bit_1base(0);
+    }
+
+    #[test]
+    fn test_bits() {
+        for val in [0x0000u16, 0x8001, 0x1234, 0xffff] {
+            let block = Block::new(val);
+            let expected = (1..=16)
+                .map(|loc| block.get_bit_1base(loc).unwrap())
+                .collect::<Vec<u16>>();
+            assert_eq!(block.bits().collect::<Vec<u16>>(), expected);
+            for (loc, bit) in block.bits_1base_enumerated() {
+                assert_eq!(bit, block.get_bit_1base(loc).unwrap());
+            }
+            assert_eq!(block.bits_1base_enumerated().count(), 16);
+        }
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let block = Block::new(0x1234);
+        assert_eq!(block.to_be_bytes(), [0x12, 0x34]);
+        assert_eq!(block.to_le_bytes(), [0x34, 0x12]);
+        assert_eq!(Block::from_be_bytes([0x12, 0x34]), block);
+        assert_eq!(Block::from_le_bytes([0x34, 0x12]), block);
+        for val in [0x0000u16, 0x0001, 0x8000, 0xabcd, 0xffff] {
+            let block = Block::new(val);
+            assert_eq!(Block::from_be_bytes(block.to_be_bytes()), block);
+            assert_eq!(Block::from_le_bytes(block.to_le_bytes()), block);
+        }
+    }
+
+    #[test]
+    fn test_binstr_validation() {
+        let err = Block::from_binstr("000001110000011").unwrap_err();
+        assert!(err.to_string().contains("\"000001110000011\""));
+        assert!(Block::from_binstr("").is_err());
+        assert!(Block::from_binstr("00000111000001100").is_err());
+        assert!(Block::from_binstr("000001110000011x").is_err());
+        assert!(Block::from_binstr("+000011100000110").is_err());
+        assert!(Block::from_binstr("0000 11100000110").is_err());
+    }
+
+    #[test]
+    fn test_hexstr_validation() {
+        assert_eq!(Block::from_hexstr("1a2b").unwrap(), Block::new(0x1a2b));
+        assert_eq!(Block::from_hexstr("ABCD").unwrap(), Block::new(0xabcd));
+        let err = Block::from_hexstr("1a2").unwrap_err();
+        assert!(err.to_string().contains("\"1a2\""));
+        assert!(Block::from_hexstr("").is_err());
+        assert!(Block::from_hexstr("1a2b3").is_err());
+        assert!(Block::from_hexstr("1a2g").is_err());
+        assert!(Block::from_hexstr("+a2b").is_err());
+        assert!(Block::from_hexstr("0x12").is_err());
+    }
+
+    #[test]
+    fn test_from_nibble_str() {
+        let block = Block::from_nibble_str("a-b-c-d", '-').unwrap();
+        assert_eq!(block, Block::new(0xabcd));
+        assert_eq!(
+            Block::from_nibble_str("1 0 F 07", ' ').unwrap(),
+            Block::new(0x10f7)
+        );
+        let err = Block::from_nibble_str("a-b-c", '-').unwrap_err();
+        assert!(err.to_string().contains("got 3"), "{err}");
+        assert!(Block::from_nibble_str("a-b-c-d-e", '-').is_err());
+        assert!(Block::from_nibble_str("a-b--d", '-').is_err());
+        assert!(Block::from_nibble_str("a-b-c-g", '-').is_err());
+        assert!(Block::from_nibble_str("a-b-c-10", '-').is_err());
+        assert!(Block::from_nibble_str("a-b-c-+d", '-').is_err());
+        assert!(Block::from_nibble_str("a,b,c,d", '-').is_err());
+    }
+
+    #[test]
+    fn test_block_hash_and_order() {
+        let blocks = [0x0003u16, 0x0001, 0x0002, 0x0001, 0x0003]
+            .into_iter()
+            .map(Block::from)
+            .collect::<HashSet<Block>>();
+        assert_eq!(blocks.len(), 3);
+
+        let mut sorted = blocks.into_iter().collect::<Vec<Block>>();
+        sorted.sort();
+        assert_eq!(
+            sorted.into_iter().map(u16::from).collect::<Vec<u16>>(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_block_formatting() {
+        let block = Block::new(0x1a2b);
+        assert_eq!(block.value(), 0x1a2b);
+        assert_eq!(format!("{block}"), "0x1a2b");
+        assert_eq!(format!("{}", Block::new(0x0007)), "0x0007");
+        assert_eq!(format!("{block:b}"), "1101000101011");
+        assert_eq!(format!("{block:016b}"), "0001101000101011");
+        assert_eq!(format!("{:#b}", Block::new(5)), "0b101");
+    }
+
+    #[test]
+    fn test_encrypt_codebook() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        let codebook = cipher.encrypt_codebook().unwrap();
+        assert_eq!(codebook.len(), 65536);
+        let plaintexts = codebook
+            .iter()
+            .map(|(pt, _)| *pt)
+            .collect::<HashSet<Block>>();
+        assert_eq!(plaintexts.len(), 65536);
+        for (pt, ct) in codebook.iter() {
+            assert_eq!(cipher.encrypt(pt).unwrap(), *ct);
+            assert_eq!(cipher.decrypt(ct).unwrap(), *pt);
+        }
+        assert_eq!(
+            codebook.iter().map(|(pt, _)| *pt).collect::<Vec<Block>>(),
+            full_plaintext_space()
+        );
+    }
+
+    #[test]
+    fn test_brute_force_k5_ignores_other_keys() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 0x0706]).unwrap();
+        let plaintexts = (0u16..8).map(Block::new).collect::<Vec<Block>>();
+        let ciphertexts = plaintexts
+            .iter()
+            .map(|pt| cipher.encrypt(pt).unwrap())
+            .collect::<Vec<Block>>();
+        let pt_locs = [5, 7, 8];
+        let u4_locs = [6, 8, 14, 16];
+
+        let with_zeros =
+            brute_force_k5(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, [0; 4]).unwrap();
+        let with_keys =
+            brute_force_k5(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, [1, 2, 3, 4]).unwrap();
+        assert_eq!(with_zeros, with_keys);
+    }
+
+    #[test]
+    fn test_brute_force_k5_partial() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) = cipher
+            .encrypt_codebook()
+            .unwrap()
+            .into_iter()
+            .step_by(7)
+            .unzip();
+        let rankings = brute_force_k5_partial(
+            &plaintexts,
+            &ciphertexts,
+            &[5, 7, 8],
+            &[6, 8, 14, 16],
+            0x0f0f,
+        )
+        .unwrap();
+        assert_eq!(rankings.len(), 256);
+        assert!(rankings
+            .iter()
+            .all(|(_, round_key)| round_key & 0xf0f0 == 0));
+        assert_eq!(rankings[0].1, 0x0f1e & 0x0f0f);
+    }
+
+    #[test]
+    fn test_signed_bias() {
+        // With all-zero keys the key bits cannot flip the sign, and Heys' trail
+        // has a bias of -1/32
+        let cipher = HeysCipher::from_keys(&[0, 0, 0, 0, 0]).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) =
+            cipher.encrypt_codebook().unwrap().into_iter().unzip();
+        let pt_locs = [5, 7, 8];
+        let u4_locs = [6, 8, 14, 16];
+        let signed = cipher
+            .get_bias_signed(&plaintexts, &ciphertexts, &pt_locs, &u4_locs)
+            .unwrap();
+        let unsigned = cipher
+            .get_bias(&plaintexts, &ciphertexts, &pt_locs, &u4_locs)
+            .unwrap();
+        assert_eq!(signed, -0.03125);
+        assert_eq!(unsigned, 0.03125);
+    }
+
+    #[test]
+    fn test_correlation() {
+        assert_eq!(bias_to_correlation(1.0 / 32.0), 1.0 / 16.0);
+        assert_eq!(bias_to_correlation(-1.0 / 32.0), -1.0 / 16.0);
+        assert_eq!(correlation_to_linear_probability(1.0 / 16.0), 1.0 / 256.0);
+        assert_eq!(correlation_to_linear_probability(-1.0 / 16.0), 1.0 / 256.0);
+
+        let cipher = HeysCipher::from_keys(&[0, 0, 0, 0, 0]).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) =
+            cipher.encrypt_codebook().unwrap().into_iter().unzip();
+        let correlation = cipher
+            .get_correlation(&plaintexts, &ciphertexts, &[5, 7, 8], &[6, 8, 14, 16])
+            .unwrap();
+        assert_eq!(correlation, -1.0 / 16.0);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_round_trip() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let json = serde_json::to_string(&cipher).unwrap();
+        assert_eq!(json, "[4660,22136,39612,57072,3870]");
+        let restored = serde_json::from_str::<HeysCipher>(&json).unwrap();
+        assert_eq!(restored.round_keys(), cipher.round_keys());
+        let pt = Block::new(0xbeef);
+        assert_eq!(restored.encrypt(&pt).unwrap(), cipher.encrypt(&pt).unwrap());
+
+        assert_eq!(serde_json::to_string(&pt).unwrap(), "48879");
+        assert_eq!(serde_json::from_str::<Block>("48879").unwrap(), pt);
+        assert!(serde_json::from_str::<HeysCipher>("[1]").is_err());
+    }
+
+    #[test]
+    fn test_from_master_key() {
+        use crate::keyschedule::{IndependentKeys, RotatingSchedule};
+
+        let round_keys = [0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e];
+        let expected = HeysCipher::from_keys(&round_keys).unwrap();
+        let cipher = HeysCipher::from_master_key(0, &IndependentKeys(round_keys)).unwrap();
+        let pt = Block::new(0xbeef);
+        assert_eq!(cipher.encrypt(&pt).unwrap(), expected.encrypt(&pt).unwrap());
+
+        let cipher = HeysCipher::from_master_key(0x0123_4567_89ab_cdef, &RotatingSchedule).unwrap();
+        let ct = cipher.encrypt(&pt).unwrap();
+        assert_eq!(cipher.decrypt(&ct).unwrap(), pt);
+    }
+
+    #[test]
+    fn test_encrypt_blocks_round_trip() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        let blocks = full_plaintext_space()
+            .into_iter()
+            .step_by(1021)
+            .collect::<Vec<Block>>();
+        let ciphertexts = cipher.encrypt_blocks(&blocks).unwrap();
+        assert_eq!(ciphertexts[1], cipher.encrypt(&blocks[1]).unwrap());
+        assert_eq!(cipher.decrypt_blocks(&ciphertexts).unwrap(), blocks);
+        assert!(cipher.encrypt_blocks(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_encrypt_pair() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        let pt = Block::new(0x1234);
+        let (ct1, ct2, output_diff) = cipher.encrypt_pair(&pt, 0x0b00).unwrap();
+        assert_eq!(ct1, cipher.encrypt(&pt).unwrap());
+        assert_eq!(ct2, cipher.encrypt(&Block::new(0x1934)).unwrap());
+        assert_eq!(output_diff, ct1.value() ^ ct2.value());
+    }
+
+    #[test]
+    fn test_count_differential() {
+        // dX = 1011 -> dY = 0010 holds with probability 1/2 in S-box 2 of the
+        // first round, which moves to S-box 3 of the second round as dX = 0100,
+        // and 0100 -> 0110 holds with probability 3/8
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc]).unwrap();
+        let count = cipher.count_differential(0x0b00, 0x0060, 2000, 0).unwrap();
+        // 2000 * 3/16 = 375 expected, against 2000 / 65536 for a random output
+        assert!(count > 250, "{count}");
+        assert_eq!(
+            count,
+            cipher.count_differential(0x0b00, 0x0060, 2000, 0).unwrap()
+        );
+        assert_eq!(cipher.count_differential(0, 0, 100, 0).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_generate_chosen_plaintexts() {
+        let plaintexts = generate_chosen_plaintexts(0x0f00, 500, 7);
+        assert_eq!(plaintexts.len(), 500);
+        assert!(plaintexts.iter().all(|pt| pt.value() & !0x0f00 == 0));
+        // Every value of the active nibble shows up
+        let nibbles = plaintexts
+            .iter()
+            .map(|pt| pt.value() >> 8)
+            .collect::<HashSet<u16>>();
+        assert_eq!(nibbles.len(), 16);
+        assert_eq!(generate_chosen_plaintexts(0x0f00, 500, 7), plaintexts);
+        assert_ne!(generate_chosen_plaintexts(0x0f00, 500, 8), plaintexts);
+        assert!(generate_chosen_plaintexts(0, 10, 7)
+            .iter()
+            .all(|pt| pt.value() == 0));
+    }
+
+    #[test]
+    fn test_bias_convergence() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let (pt_locs, u4_locs) = ([5, 7, 8], [6, 8, 14, 16]);
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) =
+            cipher.encrypt_codebook().unwrap().into_iter().unzip();
+        let exact = cipher
+            .get_bias_signed(&plaintexts, &ciphertexts, &pt_locs, &u4_locs)
+            .unwrap();
+        // Close to the 1/32 that the piling-up lemma predicts for this trail
+        assert!((exact.abs() - 1.0 / 32.0).abs() < 0.01, "{exact}");
+
+        let sizes = [64, 1024, 16384, 131072];
+        let convergence = bias_convergence(&cipher, &pt_locs, &u4_locs, &sizes, 3).unwrap();
+        assert_eq!(
+            convergence
+                .iter()
+                .map(|(size, _)| *size)
+                .collect::<Vec<usize>>(),
+            sizes
+        );
+        let errors = convergence
+            .iter()
+            .map(|(_, bias)| (bias - exact).abs())
+            .collect::<Vec<f64>>();
+        assert!(errors[3] < errors[0], "{errors:?}");
+        assert!(errors[3] < 0.005, "{errors:?}");
+        assert_eq!(
+            bias_convergence(&cipher, &pt_locs, &u4_locs, &sizes, 3).unwrap(),
+            convergence
+        );
+        assert!(bias_convergence(&cipher, &pt_locs, &u4_locs, &[0], 3).is_err());
+    }
+
+    #[test]
+    fn test_substitute_ct() {
+        for block in full_plaintext_space() {
+            assert_eq!(block.substitute_ct(&SBOX), block.substitute(&SBOX));
+            assert_eq!(
+                block.substitute_ct(&SBOX_INVERT),
+                block.substitute(&SBOX_INVERT)
+            );
+        }
+        // A bad output is masked to its nibble rather than checked
+        let mut bad_sbox = SBOX;
+        bad_sbox[3] = 0x1f;
+        let mut masked_sbox = SBOX;
+        masked_sbox[3] = 0xf;
+        let block = Block::<32>::from_word(0x1234_5678).unwrap();
+        assert_eq!(
+            block.substitute_ct(&bad_sbox),
+            block.substitute(&masked_sbox)
+        );
+    }
+
+    #[test]
+    fn test_try_substitute() {
+        let block = Block::new(0x1234);
+        assert_eq!(
+            block.try_substitute(&SBOX).unwrap(),
+            block.substitute(&SBOX)
+        );
+
+        let mut bad_sbox = SBOX;
+        bad_sbox[3] = 0x1f;
+        let err = block.try_substitute(&bad_sbox).unwrap_err();
+        assert_eq!(err.to_string(), "S-box maps 3 to 31, which is not below 16");
+        // Only the nibbles that hit the bad entry matter
+        assert!(Block::new(0x1245).try_substitute(&bad_sbox).is_ok());
+        assert!(Block::<8>::from_word(0x30)
+            .unwrap()
+            .try_substitute(&bad_sbox)
+            .is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "S-box maps 3 to 31, which is not below 16")]
+    fn test_substitute_bad_sbox() {
+        let mut bad_sbox = SBOX;
+        bad_sbox[3] = 0x1f;
+        Block::new(0x1234).substitute(&bad_sbox);
+    }
+
+    #[test]
+    fn test_brute_force_from_trail() {
+        let round_keys = [0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e];
+        let cipher = HeysCipher::from_keys(&round_keys).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) =
+            cipher.encrypt_codebook().unwrap().into_iter().unzip();
+        let lat = LinearApproximationTable::from_sbox(&SBOX);
+        let masks = [(0x0b00, 0x0400), (0x0400, 0x0500), (0x0404, 0x0505)];
+        let trail = MultiRoundTrail::from_masks(&lat, &PERMUTATION, &masks).unwrap();
+
+        let rankings = brute_force_from_trail(&plaintexts, &ciphertexts, &trail).unwrap();
+        assert_eq!(rankings.len(), 256);
+        assert!(rankings
+            .iter()
+            .all(|(_, round_key)| round_key & 0xf0f0 == 0));
+        assert_eq!(rankings[0].1, round_keys[4] & 0x0f0f);
+        assert_eq!(
+            rankings,
+            brute_force_k5_partial(
+                &plaintexts,
+                &ciphertexts,
+                &[5, 7, 8],
+                &[6, 8, 14, 16],
+                0x0f0f
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_compare_approximations() {
+        let round_keys = [0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e];
+        let cipher = HeysCipher::from_keys(&round_keys).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) =
+            cipher.sample_pairs(8000, 0).unwrap().into_iter().unzip();
+        // Heys' approximation against one with no trail behind it
+        let approxes = [(vec![5, 7, 8], vec![6, 8, 14, 16]), (vec![1], vec![1])];
+        let results =
+            compare_approximations(&plaintexts, &ciphertexts, round_keys[4], &approxes).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].approx, approxes[0]);
+        assert_eq!(results[0].rank, 1);
+        assert_eq!(
+            results[0].bias,
+            cipher
+                .get_bias_signed(&plaintexts, &ciphertexts, &[5, 7, 8], &[6, 8, 14, 16])
+                .unwrap()
+        );
+        assert!(results[0].bias.abs() > results[1].bias.abs());
+        assert!(results[1].rank > 1);
+
+        assert!(compare_approximations(&plaintexts, &ciphertexts, 0, &[])
+            .unwrap()
+            .is_empty());
+        let bad = [(vec![5, 7, 8], vec![17])];
+        assert!(compare_approximations(&plaintexts, &ciphertexts, 0, &bad).is_err());
+    }
+
+    #[test]
+    fn test_hamming_distance() {
+        let block = Block::new(0x1234);
+        assert_eq!(block.hamming_distance(&block), 0);
+        assert_eq!(block.hamming_distance(&Block::new(!0x1234)), 16);
+        assert_eq!(block.hamming_distance(&Block::new(0x1235)), 1);
+        assert_eq!(
+            Block::new(0).hamming_distance(&block),
+            block.hamming_weight()
+        );
+        let word = Block::<32>::from_word(0xffff_0000).unwrap();
+        assert_eq!(
+            word.hamming_distance(&Block::<32>::from_word(0).unwrap()),
+            16
+        );
+    }
+
+    #[test]
+    fn test_rotate() {
+        let block = Block::new(0x1234);
+        assert_eq!(block.rotate_left(0), block);
+        assert_eq!(block.rotate_right(0), block);
+        assert_eq!(block.rotate_left(4), Block::new(0x2341));
+        assert_eq!(block.rotate_right(4), Block::new(0x4123));
+        assert_eq!(block.rotate_left(16), block);
+        assert_eq!(block.rotate_right(16), block);
+        assert_eq!(block.rotate_left(5).rotate_right(5), block);
+
+        let rotation: [usize; 16] = core::array::from_fn(|i| (i + 3) % 16);
+        assert_eq!(block.rotate_left(3), block.permute(&rotation).unwrap());
+    }
+
+    #[test]
+    fn test_sample_pairs() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let pairs = cipher.sample_pairs(1000, 7).unwrap();
+        assert_eq!(pairs.len(), 1000);
+        assert_eq!(pairs, cipher.sample_pairs(1000, 7).unwrap());
+        assert_ne!(pairs, cipher.sample_pairs(1000, 8).unwrap());
+        for (pt, ct) in pairs.iter() {
+            assert_eq!(cipher.encrypt(pt).unwrap(), *ct);
+            assert_eq!(cipher.decrypt(ct).unwrap(), *pt);
+        }
+        assert!(cipher.sample_pairs(0, 7).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_codebook_iter() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let pairs = cipher
+            .codebook_iter()
+            .collect::<Result<Vec<(Block, Block)>>>()
+            .unwrap();
+        assert_eq!(pairs, cipher.encrypt_codebook().unwrap());
+
+        // Streaming the pairs gives the same rankings as the collected codebook
+        let (pt_locs, u4_locs) = ([5, 7, 8], [6, 8, 14, 16]);
+        let mut streaming = StreamingBruteForce::new(&pt_locs, &u4_locs).unwrap();
+        for pair in cipher.codebook_iter().step_by(97) {
+            let (pt, ct) = pair.unwrap();
+            streaming.update(&pt, &ct);
+        }
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) =
+            pairs.into_iter().step_by(97).unzip();
+        assert_eq!(
+            streaming.rankings(),
+            brute_force_k5(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, [0; 4]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_avalanche() {
+        let full = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let avalanche = full.avalanche(200, 0).unwrap();
+        assert!((avalanche - 0.5).abs() < 0.05, "{avalanche}");
+        assert_eq!(full.avalanche(200, 0).unwrap(), avalanche);
+
+        // One round confines a flipped bit to its own S-box, so at most 4 of
+        // the 16 output bits can change
+        let one_round = HeysCipher::from_keys(&[0x1234, 0x5678]).unwrap();
+        let avalanche = one_round.avalanche(200, 0).unwrap();
+        assert!(avalanche > 0.0 && avalanche <= 0.25, "{avalanche}");
+        assert!(full.avalanche(0, 0).is_err());
+    }
+
+    #[test]
+    fn test_output_difference_distribution() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc]).unwrap();
+        let distribution = cipher.output_difference_distribution(0).unwrap();
+        assert_eq!(distribution.len(), 1 << 16);
+        assert_eq!(distribution[0], 1 << 16);
+        assert_eq!(distribution.iter().sum::<usize>(), 1 << 16);
+
+        // Every pair is counted from both of its plaintexts, and the 2-round
+        // differential of test_count_differential holds for 3/16 of them
+        let distribution = cipher.output_difference_distribution(0x0b00).unwrap();
+        assert_eq!(distribution.iter().sum::<usize>(), 1 << 16);
+        assert_eq!(distribution[0], 0);
+        assert!(distribution.iter().all(|count| count % 2 == 0));
+        assert!(distribution[0x0060] > 10000, "{}", distribution[0x0060]);
+    }
+
+    #[test]
+    fn test_sbox() {
+        let block = Block::new(0xabcd);
+        assert_eq!(block.substitute(&SBOX), Block::new(0x6C59));
+    }
+
+    #[test]
+    fn test_permutation() {
+        assert_eq!(
+            Block::new(0b1111000000000000)
+                .permute(&PERMUTATION)
+                .unwrap(),
+            Block::new(0b1000100010001000)
+        );
+    }
+
+    #[test]
+    fn test_rankings_tie_break() {
+        // Over a single pair every candidate has a bias of +/- 0.5
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        let pt = Block::new(0x1234);
+        let ct = cipher.encrypt(&pt).unwrap();
+        let rankings = brute_force_k5(&[pt], &[ct], &[5, 7, 8], &[6, 8, 14, 16], [0; 4]).unwrap();
+        let keys = rankings.iter().map(|(_, key)| *key).collect::<Vec<u16>>();
+        assert_eq!(keys, (0..=u16::MAX).collect::<Vec<u16>>());
+    }
+
+    #[test]
+    fn test_sort_rankings_nan() {
+        let mut rankings = vec![(0.1, 3), (f64::NAN, 0), (-0.25, 7), (0.1, 1), (0.25, 2)];
+        sort_rankings(&mut rankings);
+        let keys = rankings.iter().map(|(_, key)| *key).collect::<Vec<u16>>();
+        assert_eq!(keys, vec![2, 7, 1, 3, 0]);
+        assert!(rankings[4].0.is_nan());
+    }
+
+    #[test]
+    fn test_brute_force_k5_deterministic() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 0x0706]).unwrap();
+        let plaintexts = (0u16..16).map(Block::new).collect::<Vec<Block>>();
+        let ciphertexts = plaintexts
+            .iter()
+            .map(|pt| cipher.encrypt(pt).unwrap())
+            .collect::<Vec<Block>>();
+        let pt_locs = [5, 7, 8];
+        let u4_locs = [6, 8, 14, 16];
+
+        let mut serial = (u16::MIN..=u16::MAX)
+            .map(|round_key| {
+                let guess = HeysCipher::from_keys(&[0, 0, 0, 0, round_key]).unwrap();
+                let bias = guess
+                    .get_bias_signed(&plaintexts, &ciphertexts, &pt_locs, &u4_locs)
+                    .unwrap();
+                return (bias, round_key);
+            })
+            .collect::<Vec<(f64, u16)>>();
+        serial.sort_by(|elem1, elem2| elem2.0.abs().total_cmp(&elem1.0.abs()));
+
+        let rankings =
+            brute_force_k5(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, [0; 4]).unwrap();
+        assert_eq!(rankings, serial);
+        assert_eq!(
+            rankings,
+            brute_force_k5(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, [0; 4]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_brute_force_k5_progress() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        let pt = Block::new(0x1234);
+        let ct = cipher.encrypt(&pt).unwrap();
+        let calls = AtomicUsize::new(0);
+        let progress = |_: u16, bias: f64| {
+            assert_eq!(bias.abs(), 0.5);
+            calls.fetch_add(1, Ordering::Relaxed);
+        };
+        brute_force_k5_with_progress(
+            &[pt],
+            &[ct],
+            &[5, 7, 8],
+            &[6, 8, 14, 16],
+            [0; 4],
+            Some(&progress),
+        )
+        .unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 65536);
+    }
+
+    #[test]
+    fn test_brute_force_k5_sampled() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 0x0706]).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) =
+            cipher.encrypt_codebook().unwrap().into_iter().unzip();
+        let sample = |seed| {
+            return brute_force_k5_sampled(
+                &plaintexts,
+                &ciphertexts,
+                &[5, 7, 8],
+                &[6, 8, 14, 16],
+                16,
+                seed,
+            )
+            .unwrap();
+        };
+        assert_eq!(sample(7), sample(7));
+        assert_ne!(sample(7), sample(8));
+        assert!(brute_force_k5_sampled(
+            &plaintexts[..8],
+            &ciphertexts[..8],
+            &[5, 7, 8],
+            &[6, 8, 14, 16],
+            16,
+            7
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_bias_spectrum() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) = cipher
+            .encrypt_codebook()
+            .unwrap()
+            .into_iter()
+            .step_by(11)
+            .unzip();
+        let pt_masks = [0x0b00, 0x9090];
+        let u4_masks = [0x0505, 0x4444, 0x2222];
+        let spectrum = cipher
+            .bias_spectrum(&plaintexts, &ciphertexts, &pt_masks, &u4_masks)
+            .unwrap();
+        assert_eq!(spectrum.len(), 6);
+        assert!(spectrum
+            .windows(2)
+            .all(|pair| pair[0].2.abs() >= pair[1].2.abs()));
+
+        for (pt_mask, u4_mask, pt_locs, u4_locs) in [
+            (0x0b00, 0x0505, vec![5, 7, 8], vec![6, 8, 14, 16]),
+            (0x9090, 0x4444, vec![1, 4, 9, 12], vec![2, 6, 10, 14]),
+        ] {
+            let expected = cipher
+                .get_bias_signed(&plaintexts, &ciphertexts, &pt_locs, &u4_locs)
+                .unwrap();
+            assert!(spectrum.contains(&(pt_mask, u4_mask, expected)));
+        }
+    }
+
+    #[test]
+    fn test_brute_force_k5_matches_naive() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) = cipher
+            .encrypt_codebook()
+            .unwrap()
+            .into_iter()
+            .step_by(1021)
+            .unzip();
+        let pt_locs = [1, 4, 9, 12];
+        let u4_locs = [2, 6, 10, 14];
+        let naive = |candidates: &[u16]| {
+            let mut rankings = candidates
+                .iter()
+                .map(|round_key| {
+                    let guess = HeysCipher::from_keys(&[0, 0, 0, 0, *round_key]).unwrap();
+                    let bias = guess
+                        .get_bias_signed(&plaintexts, &ciphertexts, &pt_locs, &u4_locs)
+                        .unwrap();
+                    return (bias, *round_key);
+                })
+                .collect::<Vec<(f64, u16)>>();
+            sort_rankings(&mut rankings);
+            return rankings;
+        };
+
+        let all_keys = (u16::MIN..=u16::MAX).collect::<Vec<u16>>();
+        let rankings =
+            brute_force_k5(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, [0; 4]).unwrap();
+        assert_eq!(rankings, naive(&all_keys));
+
+        let partial_keys = all_keys
+            .into_iter()
+            .filter(|round_key| round_key & 0x0f0f == 0)
+            .collect::<Vec<u16>>();
+        let rankings =
+            brute_force_k5_partial(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, 0xf0f0).unwrap();
+        assert_eq!(rankings, naive(&partial_keys));
+    }
+
+    #[test]
+    fn test_brute_force_k5_replicates_inactive_nibbles() {
+        // Only S-boxes 2 and 4 are active, so the 256 patterns of their key
+        // nibbles are counted and replicated across the other 8 bits
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) = cipher
+            .encrypt_codebook()
+            .unwrap()
+            .into_iter()
+            .step_by(101)
+            .unzip();
+        let pt_locs = [5, 7, 8];
+        let u4_locs = [6, 8, 14, 16];
+        let rankings =
+            brute_force_k5(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, [0; 4]).unwrap();
+        assert_eq!(rankings.len(), 1 << 16);
+
+        // Every key sharing the active nibbles of the best one ties with it
+        let (best_bias, best_key) = rankings[0];
+        let ties = rankings
+            .iter()
+            .filter(|(bias, _)| *bias == best_bias)
+            .map(|(_, round_key)| *round_key)
+            .collect::<HashSet<u16>>();
+        for inactive in (u16::MIN..=u16::MAX).filter(|bits| bits & 0x0f0f == 0) {
+            assert!(ties.contains(&((best_key & 0x0f0f) | inactive)));
+        }
+
+        // A sparse subset of the keys ranks the same as the naive computation
+        let subset = (u16::MIN..=u16::MAX).step_by(257).collect::<HashSet<u16>>();
+        let mut naive = subset
+            .iter()
+            .map(|round_key| {
+                let guess = HeysCipher::from_keys(&[0, 0, 0, 0, *round_key]).unwrap();
+                let bias = guess
+                    .get_bias_signed(&plaintexts, &ciphertexts, &pt_locs, &u4_locs)
+                    .unwrap();
+                return (bias, *round_key);
+            })
+            .collect::<Vec<(f64, u16)>>();
+        sort_rankings(&mut naive);
+        let fast = rankings
+            .into_iter()
+            .filter(|(_, round_key)| subset.contains(round_key))
+            .collect::<Vec<(f64, u16)>>();
+        assert_eq!(fast, naive);
+    }
+
+    #[test]
+    fn test_brute_force_k5_counts() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) = cipher
+            .encrypt_codebook()
+            .unwrap()
+            .into_iter()
+            .step_by(7)
+            .unzip();
+        let (pt_locs, u4_locs) = ([5, 7, 8], [6, 8, 14, 16]);
+        let counts = brute_force_k5_counts(&plaintexts, &ciphertexts, &pt_locs, &u4_locs).unwrap();
+        assert_eq!(counts.len(), 65536);
+        assert!(counts
+            .iter()
+            .enumerate()
+            .all(|(i, (key, _, total))| *key as usize == i && *total == plaintexts.len()));
+
+        let rankings =
+            brute_force_k5(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, [0; 4]).unwrap();
+        let (bias, top_key) = rankings[0];
+        let (_, satisfied, total) = counts[top_key as usize];
+        assert!((satisfied as f64 / total as f64 - (0.5 + bias)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_best_k5() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) = cipher
+            .encrypt_codebook()
+            .unwrap()
+            .into_iter()
+            .step_by(1021)
+            .unzip();
+        for (pt_locs, u4_locs) in [
+            (vec![5, 7, 8], vec![6, 8, 14, 16]),
+            (vec![1, 4, 9, 12], vec![2, 6, 10, 14]),
+        ] {
+            let rankings =
+                brute_force_k5(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, [0; 4]).unwrap();
+            let best = best_k5(&plaintexts, &ciphertexts, &pt_locs, &u4_locs).unwrap();
+            assert_eq!(best, rankings[0]);
+        }
+        assert!(best_k5(&[], &[], &[5, 7, 8], &[6, 8, 14, 16]).is_err());
+    }
+
+    #[test]
+    fn test_brute_force_k5_above() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) = cipher
+            .encrypt_codebook()
+            .unwrap()
+            .into_iter()
+            .step_by(3)
+            .unzip();
+        let (pt_locs, u4_locs) = ([5, 7, 8], [6, 8, 14, 16]);
+        let above = |min_bias| {
+            return brute_force_k5_above(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, min_bias)
+                .unwrap();
+        };
+        let high = above(0.025);
+        let low = above(0.015);
+        assert!(!high.is_empty());
+        assert!(high.len() < low.len());
+        assert!(high.iter().all(|entry| low.contains(entry)));
+        assert!(low.iter().all(|(bias, _)| bias.abs() >= 0.015));
+
+        let rankings =
+            brute_force_k5(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, [0; 4]).unwrap();
+        assert_eq!(low, rankings[..low.len()]);
+        assert_eq!(above(0.0), rankings);
+    }
+
+    #[test]
+    fn test_reduced_attack() {
+        let round_keys = [0x1234, 0x5678, 0x9abc];
+        let cipher = HeysCipher::reduced(2, &round_keys).unwrap();
+        assert_eq!(cipher.num_rounds(), 2);
+        assert!(HeysCipher::reduced(2, &round_keys[..2]).is_err());
+        assert!(HeysCipher::reduced(0, &round_keys[..1]).is_err());
+
+        // A 1-round approximation of S2 onto a single output bit reaches the
+        // second U2 bit of the S-box that bit is permuted to, so each of them
+        // recovers one nibble of the last round key
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) =
+            cipher.encrypt_codebook().unwrap().into_iter().unzip();
+        let lat = LinearApproximationTable::from_sbox(&SBOX);
+        let mut last_key = 0;
+        for (sbox, (input_mask, output_mask)) in [(0x9, 0x8), (0xa, 0x4), (0xc, 0x2), (0xa, 0x1)]
+            .into_iter()
+            .enumerate()
+        {
+            let masks = [(input_mask << 8, output_mask << 8)];
+            let trail = MultiRoundTrail::from_masks(&lat, &PERMUTATION, &masks).unwrap();
+            let active = 0xf000 >> (4 * sbox);
+            assert_eq!(trail.u_mask, 0x4000 >> (4 * sbox));
+            let rankings = brute_force_k5_partial(
+                &plaintexts,
+                &ciphertexts,
+                &trail.pt_locs(),
+                &trail.u4_locs(),
+                active,
+            )
+            .unwrap();
+            assert_eq!(rankings[0].0.abs(), 0.25);
+            last_key |= rankings[0].1;
+        }
+        assert_eq!(last_key, round_keys[2]);
+    }
+
+    #[test]
+    fn test_bias_accumulator() {
+        let round_keys = [0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e];
+        let cipher = HeysCipher::from_keys(&round_keys).unwrap();
+        let (pt_locs, u4_locs) = ([5, 7, 8], [6, 8, 14, 16]);
+        let mut accumulator = BiasAccumulator::new(&pt_locs, &u4_locs).unwrap();
+        assert!(accumulator.bias().is_nan());
+
+        let codebook = cipher.encrypt_codebook().unwrap();
+        for (pt, ct) in &codebook {
+            accumulator.update(pt, ct, round_keys[4]);
+        }
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) = codebook.into_iter().unzip();
+        assert_eq!(accumulator.total(), 65536);
+        assert_eq!(
+            accumulator.bias(),
+            cipher
+                .get_bias_signed(&plaintexts, &ciphertexts, &pt_locs, &u4_locs)
+                .unwrap()
+        );
+        assert_eq!(
+            accumulator.bias().abs(),
+            cipher
+                .get_bias(&plaintexts, &ciphertexts, &pt_locs, &u4_locs)
+                .unwrap()
+        );
+        assert!(BiasAccumulator::new(&[], &u4_locs).is_err());
+    }
+
+    #[test]
+    fn test_streaming_brute_force() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let (pt_locs, u4_locs) = ([5, 7, 8], [6, 8, 14, 16]);
+        let mut streaming = StreamingBruteForce::new(&pt_locs, &u4_locs).unwrap();
+        let codebook = cipher.encrypt_codebook().unwrap();
+        for (pt, ct) in &codebook {
+            streaming.update(pt, ct);
+        }
+        assert_eq!(streaming.total(), 65536);
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) = codebook.into_iter().unzip();
+        let rankings =
+            brute_force_k5(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, [0; 4]).unwrap();
+        assert_eq!(streaming.rankings(), rankings);
+        assert!(StreamingBruteForce::new(&pt_locs, &[17]).is_err());
+    }
+
+    #[test]
+    fn test_get_bit_conventions() {
+        // Only the second most significant bit is set
+        let block = Block::new(0x4000);
+        let conventions = [
+            (BitConvention::BigEndian1Based, 2),
+            (BitConvention::BigEndian0Based, 1),
+            (BitConvention::LittleEndian0Based, 14),
+            (BitConvention::LittleEndian1Based, 15),
+        ];
+        for (convention, index) in conventions {
+            for other in 0..=17 {
+                let bit = block.get_bit(other, convention);
+                if other == index {
+                    assert_eq!(bit.unwrap(), 1);
+                } else {
+                    assert!(bit.map_or(true, |bit| bit == 0));
+                }
+            }
+        }
+        assert!(block.get_bit(0, BitConvention::BigEndian1Based).is_err());
+        assert!(block.get_bit(16, BitConvention::BigEndian1Based).is_ok());
+        assert!(block
+            .get_bit(16, BitConvention::LittleEndian0Based)
+            .is_err());
+        assert!(block.get_bit(0, BitConvention::LittleEndian1Based).is_err());
+        assert_eq!(
+            Block::<8>::from_word(0x01)
+                .unwrap()
+                .get_bit(8, BitConvention::BigEndian1Based)
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_set_bit_1base() {
+        let mut block = Block::new(0);
+        for loc in [5, 7, 8] {
+            block.set_bit_1base(loc, 1).unwrap();
+        }
+        assert_eq!(block, Block::new(0x0b00));
+        block.set_bit_1base(5, 0).unwrap();
+        assert_eq!(block, Block::new(0x0300));
+        for loc in 1..=16 {
+            for value in [0, 1] {
+                let updated = block.with_bit_1base(loc, value).unwrap();
+                assert_eq!(updated.get_bit_1base(loc).unwrap(), value);
+                assert!(updated.mix_key(block.value()).hamming_weight() <= 1);
+            }
+        }
+        assert!(block.set_bit_1base(0, 1).is_err());
+        assert!(block.set_bit_1base(17, 1).is_err());
+        assert!(block.with_bit_1base(1, 2).is_err());
+        assert_eq!(block, Block::new(0x0300));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_bias_interval_narrows() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) =
+            cipher.encrypt_codebook().unwrap().into_iter().unzip();
+        let (pt_locs, u4_locs) = ([5, 7, 8], [6, 8, 14, 16]);
+        let mut previous_width = f64::INFINITY;
+        for n in [100, 1000, 10000, 65536] {
+            let (bias, lower, upper) = cipher
+                .get_bias_with_interval(
+                    &plaintexts[..n],
+                    &ciphertexts[..n],
+                    &pt_locs,
+                    &u4_locs,
+                    0.95,
+                )
+                .unwrap();
+            assert!(lower <= bias && bias <= upper);
+            assert!(upper - lower < previous_width);
+            previous_width = upper - lower;
+        }
+        assert!(cipher
+            .get_bias_with_interval(&plaintexts, &ciphertexts, &pt_locs, &u4_locs, 1.5)
+            .is_err());
+    }
+
+    #[test]
+    fn test_bitxor() {
+        let a = Block::new(0x1234);
+        let b = Block::new(0xbeef);
+        assert_eq!(a ^ b, Block::new(0x1234 ^ 0xbeef));
+        assert_eq!((a ^ b) ^ b, a);
+        assert_eq!(a ^ 0x0b00, Block::new(0x1934));
+        assert_eq!((a ^ 0xbeef) ^ 0xbeef, a);
+        assert_eq!(a ^ 0xbeef, a.mix_key(0xbeef));
+        let wide = Block::<32>::from_word(0xdeadbeef).unwrap();
+        assert_eq!(wide ^ wide, Block::<32>::from_word(0).unwrap());
+    }
+
+    #[test]
+    fn test_best_linear_approximation() {
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let (pt_mask, u4_mask, bias) = cipher.best_linear_approximation().unwrap();
+        assert!(bias.abs() >= 1.0 / 32.0);
+        assert_ne!(pt_mask, 0);
+        assert_ne!(u4_mask, 0);
+        let one_round = HeysCipher::reduced(1, &[1, 2]).unwrap();
+        assert!(one_round.best_linear_approximation().is_none());
+    }
+
+    #[test]
+    fn test_random_cipher() {
+        let cipher = HeysCipher::random(7);
+        assert_eq!(cipher.num_rounds(), 4);
+        assert_eq!(cipher.round_keys(), HeysCipher::random(7).round_keys());
+        assert_ne!(cipher.round_keys(), HeysCipher::random(8).round_keys());
+    }
+
+    #[test]
+    fn test_active_sboxes() {
+        assert_eq!(count_active_sboxes(0x0000), 0);
+        assert!(active_sbox_indices(0x0000).is_empty());
+        assert_eq!(count_active_sboxes(0x0b00), 1);
+        assert_eq!(active_sbox_indices(0x0b00), vec![1]);
+        assert_eq!(count_active_sboxes(0x0505), 2);
+        assert_eq!(active_sbox_indices(0x0505), vec![1, 3]);
+        assert_eq!(count_active_sboxes(0x8421), 4);
+        assert_eq!(active_sbox_indices(0x8421), vec![0, 1, 2, 3]);
+        assert_eq!(active_sbox_mask(0x0505), 0x0f0f);
+    }
+
+    #[test]
+    fn test_sbox_apply() {
+        assert_eq!(sbox_apply(&SBOX, 0xB), 0xC);
+        for (input, output) in SBOX_PAIRS {
+            assert_eq!(sbox_apply(&SBOX, input), output);
+        }
+    }
+
+    #[test]
+    fn test_sbox_invert() {
+        assert_eq!(sbox_invert(&SBOX, 0xE).unwrap(), 0x0);
+        for output in 0..16 {
+            assert_eq!(
+                sbox_invert(&SBOX, output).unwrap(),
+                SBOX_INVERT[output as usize]
+            );
+        }
+        assert!(sbox_invert(&SBOX, 16).is_err());
+        let mut repeated = SBOX;
+        repeated[1] = repeated[0];
+        assert!(sbox_invert(&repeated, 0x4).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_sbox_apply_out_of_range() {
+        sbox_apply(&SBOX, 16);
+    }
+
+    #[test]
+    fn test_brute_force_k5_with_event() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 0x0706]).unwrap();
+        let plaintexts = (0u16..32).map(Block::new).collect::<Vec<Block>>();
+        let ciphertexts = cipher.encrypt_blocks(&plaintexts).unwrap();
+        let linear_approx = |pt: &Block, ct: &Block, k5: u16| {
+            let u4 = ct.mix_key(k5).substitute(&SBOX_INVERT);
+            return pt.parity_of_mask(0x0b00) == u4.parity_of_mask(0x0505);
+        };
+        let rankings = brute_force_k5_with_event(&plaintexts, &ciphertexts, linear_approx).unwrap();
+        assert_eq!(
+            rankings,
+            brute_force_k5(
+                &plaintexts,
+                &ciphertexts,
+                &[5, 7, 8],
+                &[6, 8, 14, 16],
+                [0; 4]
+            )
+            .unwrap()
+        );
+        assert!(brute_force_k5_with_event(&plaintexts[1..], &ciphertexts, linear_approx).is_err());
+    }
+
+    #[test]
+    fn test_nibbles() {
+        let block = Block::new(0x1a2b);
+        assert_eq!(block.nibbles(), [0x1, 0xa, 0x2, 0xb]);
+        assert_eq!(Block::from_nibbles([0x1, 0xa, 0x2, 0xb]), block);
+        assert_eq!(Block::from_nibbles([0x11, 0, 0, 0]), Block::new(0x1000));
+        let substituted = block.substitute(&SBOX);
+        assert_eq!(
+            substituted.nibbles(),
+            block.nibbles().map(|nibble| sbox_apply(&SBOX, nibble))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_nibbles_round_trip(val in any::<u16>()) {
+            let block = Block::new(val);
+            prop_assert_eq!(Block::from_nibbles(block.nibbles()), block);
+        }
+    }
+
+    #[test]
+    fn test_single_bit_u4_spectrum() {
+        // With identity components U4 is the plaintext XOR the first four
+        // keys, so P[3] always matches U4[3] and no other U4 bit
+        let identity_sbox: [u16; 16] = core::array::from_fn(|i| i as u16);
+        let identity_permutation: [usize; 16] = core::array::from_fn(|i| i);
+        let round_keys = [0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e];
+        let cipher =
+            HeysCipher::with_components(&round_keys, &identity_sbox, &identity_permutation)
+                .unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) = cipher
+            .encrypt_codebook()
+            .unwrap()
+            .into_iter()
+            .step_by(3)
+            .unzip();
+        let spectrum = cipher
+            .single_bit_u4_spectrum(&plaintexts, &ciphertexts, &[3], 0x0f1e)
+            .unwrap();
+        // K1 ^ K2 ^ K3 ^ K4 happens to be 0, so the relation always holds
+        assert_eq!(spectrum[2], 0.5);
+        for (i, bias) in spectrum.iter().enumerate() {
+            let expected = cipher
+                .get_bias_signed(&plaintexts, &ciphertexts, &[3], &[i as u8 + 1])
+                .unwrap();
+            assert_eq!(*bias, expected);
+            if i != 2 {
+                assert!(bias.abs() < 0.01, "U4[{}] has bias {bias}", i + 1);
+            }
+        }
+
+        assert!(cipher
+            .single_bit_u4_spectrum(&plaintexts, &ciphertexts[1..], &[3], 0)
+            .is_err());
+        assert!(cipher
+            .single_bit_u4_spectrum(&plaintexts, &ciphertexts, &[17], 0)
+            .is_err());
     }
 }
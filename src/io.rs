@@ -0,0 +1,209 @@
+//! Reading and writing plaintext/ciphertext datasets
+use crate::heys::Block;
+use std::error::Error;
+use std::fs;
+
+type Result<T> = core::result::Result<T, Box<dyn Error>>;
+
+/// Read a file with one 16-character binary string per line
+pub fn read_blocks_binstr(path: &str) -> Result<Vec<Block>> {
+    let contents = fs::read_to_string(path)?;
+    return parse_lines(path, &contents, Block::from_binstr);
+}
+
+/// Read a file with one 4-character hex string per line
+pub fn read_blocks_hex(path: &str) -> Result<Vec<Block>> {
+    let contents = fs::read_to_string(path)?;
+    return parse_lines(path, &contents, Block::from_hexstr);
+}
+
+/// Read a file of either binary strings or hex strings, detected from the
+/// length of the first line. Every line must then be in that format.
+pub fn read_blocks_auto(path: &str) -> Result<Vec<Block>> {
+    let contents = fs::read_to_string(path)?;
+    let parse = match contents.lines().next().map(|line| line.chars().count()) {
+        None => return Ok(vec![]),
+        Some(16) => Block::from_binstr,
+        Some(4) => Block::from_hexstr,
+        Some(len) => {
+            return Err(format!(
+                "{path}:1: Expected 16 binary digits or 4 hex digits but got {len}"
+            )
+            .into())
+        }
+    };
+    return parse_lines(path, &contents, parse);
+}
+
+/// Parse every line, prefixing errors with the path and line number
+fn parse_lines(
+    path: &str,
+    contents: &str,
+    parse: fn(&str) -> crate::error::Result<Block>,
+) -> Result<Vec<Block>> {
+    let blocks = contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            return parse(line).map_err(|err| format!("{path}:{}: {err}", i + 1).into());
+        })
+        .collect::<Result<Vec<Block>>>()?;
+    return Ok(blocks);
+}
+
+/// Write one binary string per line, the inverse of `read_blocks_binstr`
+pub fn write_blocks_binstr(path: &str, blocks: &[Block]) -> Result<()> {
+    let contents = blocks
+        .iter()
+        .map(|block| block.to_binstr() + "\n")
+        .collect::<String>();
+    fs::write(path, contents)?;
+    return Ok(());
+}
+
+/// Read a file of raw big-endian 2-byte blocks
+pub fn read_blocks_binary(path: &str) -> Result<Vec<Block>> {
+    return read_blocks_packed_be(path);
+}
+
+/// Read a file of contiguous big-endian 2-byte blocks with no separators
+pub fn read_blocks_packed_be(path: &str) -> Result<Vec<Block>> {
+    return read_blocks_packed(path, Block::from_be_bytes);
+}
+
+/// Read a file of contiguous little-endian 2-byte blocks with no separators
+pub fn read_blocks_packed_le(path: &str) -> Result<Vec<Block>> {
+    return read_blocks_packed(path, Block::from_le_bytes);
+}
+
+/// Decode every 2-byte chunk of the file, which must have an even length
+fn read_blocks_packed(path: &str, decode: fn([u8; 2]) -> Block) -> Result<Vec<Block>> {
+    let bytes = fs::read(path)?;
+    if bytes.len() % 2 != 0 {
+        return Err(format!(
+            "{path}: {} bytes is not a whole number of blocks",
+            bytes.len()
+        )
+        .into());
+    }
+    let blocks = bytes
+        .chunks_exact(2)
+        .map(|chunk| decode([chunk[0], chunk[1]]))
+        .collect::<Vec<Block>>();
+    return Ok(blocks);
+}
+
+/// Write contiguous big-endian 2-byte blocks, the inverse of
+/// `read_blocks_packed_be`
+pub fn write_blocks_packed_be(path: &str, blocks: &[Block]) -> Result<()> {
+    return write_blocks_packed(path, blocks, Block::to_be_bytes);
+}
+
+/// Write contiguous little-endian 2-byte blocks, the inverse of
+/// `read_blocks_packed_le`
+pub fn write_blocks_packed_le(path: &str, blocks: &[Block]) -> Result<()> {
+    return write_blocks_packed(path, blocks, Block::to_le_bytes);
+}
+
+fn write_blocks_packed(path: &str, blocks: &[Block], encode: fn(&Block) -> [u8; 2]) -> Result<()> {
+    let bytes = blocks.iter().flat_map(encode).collect::<Vec<u8>>();
+    fs::write(path, bytes)?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_binstr_round_trip() {
+        let path = env::temp_dir().join("cryptanalysis_test_binstr_round_trip.txt");
+        let path = path.to_str().unwrap();
+        let blocks = [0x0000u16, 0x0706, 0x1234, 0xffff]
+            .into_iter()
+            .map(Block::new)
+            .collect::<Vec<Block>>();
+        write_blocks_binstr(path, &blocks).unwrap();
+        assert_eq!(read_blocks_binstr(path).unwrap(), blocks);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_binstr_malformed_line() {
+        let path = env::temp_dir().join("cryptanalysis_test_binstr_malformed_line.txt");
+        let path = path.to_str().unwrap();
+        fs::write(path, "0000011100000110\n00000111000001x0\n").unwrap();
+        let err = read_blocks_binstr(path).unwrap_err().to_string();
+        assert!(err.contains(&format!("{path}:2:")), "{err}");
+        assert!(err.contains("00000111000001x0"), "{err}");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_read_blocks_hex() {
+        let path = env::temp_dir().join("cryptanalysis_test_read_blocks_hex.txt");
+        let path = path.to_str().unwrap();
+        fs::write(path, "1a2b\nFFFF\n0000\n").unwrap();
+        let expected = [0x1a2b, 0xffff, 0x0000].map(Block::new);
+        assert_eq!(read_blocks_hex(path).unwrap(), expected);
+        assert_eq!(read_blocks_auto(path).unwrap(), expected);
+        assert!(read_blocks_binstr(path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_read_blocks_auto() {
+        let path = env::temp_dir().join("cryptanalysis_test_read_blocks_auto.txt");
+        let path = path.to_str().unwrap();
+        fs::write(path, "0000011100000110\n1111111111111111\n").unwrap();
+        let expected = [0x0706, 0xffff].map(Block::new);
+        assert_eq!(read_blocks_auto(path).unwrap(), expected);
+
+        // The format is fixed by the first line
+        fs::write(path, "0000011100000110\n1a2b\n").unwrap();
+        let err = read_blocks_auto(path).unwrap_err().to_string();
+        assert!(err.contains(&format!("{path}:2:")), "{err}");
+        fs::write(path, "1a2b\n0000011100000110\n").unwrap();
+        assert!(read_blocks_auto(path).is_err());
+        fs::write(path, "1a2b3\n").unwrap();
+        assert!(read_blocks_auto(path).is_err());
+        fs::write(path, "").unwrap();
+        assert!(read_blocks_auto(path).unwrap().is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_read_blocks_binary() {
+        let path = env::temp_dir().join("cryptanalysis_test_read_blocks_binary.bin");
+        let path = path.to_str().unwrap();
+        fs::write(path, [0x12, 0x34, 0xab, 0xcd]).unwrap();
+        let blocks = read_blocks_binary(path).unwrap();
+        assert_eq!(blocks, vec![Block::new(0x1234), Block::new(0xabcd)]);
+
+        fs::write(path, [0x12, 0x34, 0xab]).unwrap();
+        let err = read_blocks_binary(path).unwrap_err();
+        assert!(err.to_string().contains("3 bytes"), "{err}");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_packed_round_trip() {
+        let path = env::temp_dir().join("cryptanalysis_test_packed_round_trip.bin");
+        let path = path.to_str().unwrap();
+        let blocks = [0x0000u16, 0x0706, 0x1234, 0xffff].map(Block::new);
+
+        write_blocks_packed_le(path, &blocks).unwrap();
+        assert_eq!(fs::read(path).unwrap()[2..4], [0x06, 0x07]);
+        assert_eq!(read_blocks_packed_le(path).unwrap(), blocks);
+        assert_ne!(read_blocks_packed_be(path).unwrap(), blocks);
+
+        write_blocks_packed_be(path, &blocks).unwrap();
+        assert_eq!(fs::read(path).unwrap()[2..4], [0x07, 0x06]);
+        assert_eq!(read_blocks_packed_be(path).unwrap(), blocks);
+
+        fs::write(path, [0x12, 0x34, 0xab]).unwrap();
+        assert!(read_blocks_packed_le(path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+//! Key schedules deriving the five Heys round keys from a master key
+
+/// Derives the round keys of a 4-round Heys cipher from a master key
+pub trait KeySchedule {
+    fn round_keys(&self, master: u64) -> [u16; 5];
+}
+
+/// Five independent round keys; the master key is ignored since 80 bits of
+/// key material do not fit in it
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct IndependentKeys(pub [u16; 5]);
+
+impl KeySchedule for IndependentKeys {
+    fn round_keys(&self, _master: u64) -> [u16; 5] {
+        return self.0;
+    }
+}
+
+/// Round key i is the 16-bit window at the top of the master key rotated left
+/// by 12i bits, so consecutive round keys share 4 bits
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub struct RotatingSchedule;
+
+impl KeySchedule for RotatingSchedule {
+    fn round_keys(&self, master: u64) -> [u16; 5] {
+        let mut round_keys = [0; 5];
+        for (i, round_key) in round_keys.iter_mut().enumerate() {
+            *round_key = (master.rotate_left(12 * i as u32) >> 48) as u16;
+        }
+        return round_keys;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_independent_keys() {
+        let schedule = IndependentKeys([1, 2, 3, 4, 5]);
+        assert_eq!(schedule.round_keys(0), [1, 2, 3, 4, 5]);
+        assert_eq!(schedule.round_keys(u64::MAX), [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_rotating_schedule() {
+        let round_keys = RotatingSchedule.round_keys(0x0123_4567_89ab_cdef);
+        assert_eq!(round_keys, [0x0123, 0x3456, 0x6789, 0x9abc, 0xcdef]);
+        for pair in round_keys.windows(2) {
+            assert_eq!(pair[0] & 0xf, pair[1] >> 12);
+        }
+    }
+}
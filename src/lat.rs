@@ -0,0 +1,149 @@
+//! Linear approximation table (LAT) of a 4-bit S-box
+use crate::stats::piling_up;
+use alloc::vec::Vec;
+
+/// For every input mask `a` and output mask `b`, the number of inputs `X` out of
+/// 16 for which the input bits selected by `a` XOR to the same value as the
+/// output bits selected by `b`.
+///
+/// Masks follow Heys' convention: the most significant bit of a nibble is X1
+/// (or Y1), so the input mask 0xB selects X1, X3, and X4.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LinearApproximationTable {
+    counts: [[u16; 16]; 16],
+}
+
+impl LinearApproximationTable {
+    pub fn from_sbox(sbox: &[u16; 16]) -> Self {
+        let mut counts = [[0u16; 16]; 16];
+        for (input_mask, row) in counts.iter_mut().enumerate() {
+            for (output_mask, count) in row.iter_mut().enumerate() {
+                *count = (0..16usize)
+                    .filter(|x| {
+                        let input_sum = (x & input_mask).count_ones();
+                        let output_sum = (sbox[*x] as usize & output_mask).count_ones();
+                        return input_sum % 2 == output_sum % 2;
+                    })
+                    .count() as u16;
+            }
+        }
+        return Self { counts };
+    }
+
+    /// The raw count N_L(a, b); masks are taken modulo 16
+    pub fn count(&self, input_mask: u16, output_mask: u16) -> u16 {
+        return self.counts[(input_mask % 16) as usize][(output_mask % 16) as usize];
+    }
+
+    /// The bias (N_L(a, b) - 8) / 16 of the approximation
+    pub fn bias(&self, input_mask: u16, output_mask: u16) -> f64 {
+        return (self.count(input_mask, output_mask) as f64 - 8.0) / 16.0;
+    }
+
+    /// The linearity `L = max |W(a, b)|` over every input mask `a` and non-zero
+    /// output mask `b`, where `W(a, b) = 2 * (N_L(a, b) - 8)` is the Walsh
+    /// coefficient. An affine S-box has the maximum of 16; lower is more
+    /// resistant to linear cryptanalysis.
+    pub fn linearity(&self) -> u32 {
+        return (0u16..16)
+            .flat_map(|input_mask| (1u16..16).map(move |output_mask| (input_mask, output_mask)))
+            .map(|(input_mask, output_mask)| {
+                return 2 * (self.count(input_mask, output_mask) as i32 - 8).unsigned_abs();
+            })
+            .max()
+            .unwrap_or(0);
+    }
+
+    /// The nonlinearity `NL = 2^(n - 1) - L / 2 = 8 - L / 2` for n = 4 input
+    /// bits: the Hamming distance from the closest affine function to any
+    /// non-zero combination of the output bits. The best 4-bit S-boxes reach 4.
+    pub fn nonlinearity(&self) -> u32 {
+        return 8 - self.linearity() / 2;
+    }
+
+    /// The n non-trivial approximations (both masks non-zero) with the highest
+    /// bias magnitude, as (input mask, output mask, bias) sorted descending by
+    /// magnitude. Ties are kept in ascending mask order.
+    pub fn best_approximations(&self, n: usize) -> Vec<(u16, u16, f64)> {
+        let mut approximations = (1u16..16)
+            .flat_map(|input_mask| (1u16..16).map(move |output_mask| (input_mask, output_mask)))
+            .map(|(input_mask, output_mask)| {
+                (input_mask, output_mask, self.bias(input_mask, output_mask))
+            })
+            .collect::<Vec<(u16, u16, f64)>>();
+        approximations.sort_by(|(_, _, bias1), (_, _, bias2)| {
+            return bias2.abs().total_cmp(&bias1.abs());
+        });
+        approximations.truncate(n);
+        return approximations;
+    }
+}
+
+/// Predict the bias of a linear trail by combining the biases of its active
+/// S-box approximations with the piling-up lemma:
+///
+/// bias = 2^(n-1) * (bias_1 * bias_2 * ... * bias_n)
+///
+/// `masks` lists the (input mask, output mask) of every active S-box across all
+/// rounds of the trail. The bias is signed; an empty trail has bias 1/2.
+pub fn predict_trail_bias(lat: &LinearApproximationTable, masks: &[(u16, u16)]) -> f64 {
+    let biases = masks
+        .iter()
+        .map(|(input_mask, output_mask)| lat.bias(*input_mask, *output_mask))
+        .collect::<Vec<f64>>();
+    return piling_up(&biases);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heys::SBOX;
+
+    #[test]
+    fn test_heys_lat_entry() {
+        // Heys section 3.3: X1 + X3 + X4 = Y2 holds for 12 of 16 inputs
+        let lat = LinearApproximationTable::from_sbox(&SBOX);
+        assert_eq!(lat.count(0xB, 0x4), 12);
+        assert_eq!(lat.bias(0xB, 0x4), 0.25);
+    }
+
+    #[test]
+    fn test_predict_trail_bias() {
+        // Heys section 3.4: S12, S22, S32, and S34 combine to a bias of -1/32
+        let lat = LinearApproximationTable::from_sbox(&SBOX);
+        let masks = [(0xB, 0x4), (0x4, 0x5), (0x4, 0x5), (0x4, 0x5)];
+        assert_eq!(predict_trail_bias(&lat, &masks), -1.0 / 32.0);
+        assert_eq!(predict_trail_bias(&lat, &masks[..1]), 0.25);
+        assert_eq!(predict_trail_bias(&lat, &[]), 0.5);
+    }
+
+    #[test]
+    fn test_best_approximations() {
+        let lat = LinearApproximationTable::from_sbox(&SBOX);
+        let best = lat.best_approximations(3);
+        assert_eq!(best.len(), 3);
+        assert!(best
+            .windows(2)
+            .all(|pair| pair[0].2.abs() >= pair[1].2.abs()));
+        for (input_mask, output_mask, bias) in best {
+            assert!(input_mask != 0 && output_mask != 0);
+            assert_eq!(bias.abs(), 0.375);
+        }
+    }
+
+    #[test]
+    fn test_linearity() {
+        // Heys' S-box has entries of 2 and 14, e.g. N_L(2, E) = 2 and
+        // N_L(1, 7) = 14
+        let lat = LinearApproximationTable::from_sbox(&SBOX);
+        assert_eq!(lat.count(0x2, 0xE), 2);
+        assert_eq!(lat.count(0x1, 0x7), 14);
+        assert_eq!(lat.linearity(), 12);
+        assert_eq!(lat.nonlinearity(), 2);
+
+        let identity = core::array::from_fn(|x| x as u16);
+        let lat = LinearApproximationTable::from_sbox(&identity);
+        assert_eq!(lat.linearity(), 16);
+        assert_eq!(lat.nonlinearity(), 0);
+    }
+}
@@ -1 +1,17 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod attack;
+pub mod ddt;
+pub mod error;
 pub mod heys;
+#[cfg(feature = "std")]
+pub mod io;
+pub mod keyschedule;
+pub mod lat;
+pub mod recovery;
+pub mod sbox;
+pub mod spn;
+pub mod stats;
+pub mod trail;
@@ -1,16 +1,191 @@
-use cryptanalysis::heys::{Block, HeysCipher};
-
-fn main() {
-    let round_keys = [0, 0, 0, 0, 0];
-    let cipher = HeysCipher::from_keys(&round_keys).unwrap();
-    let plaintexts = (u16::MIN..=u16::MAX)
-        .map(|val| Block::new(val))
-        .collect::<Vec<Block>>();
-    let ciphertexts = plaintexts
+use clap::{Args, Parser, Subcommand};
+use cryptanalysis::heys::{
+    active_sbox_mask, brute_force_k5, brute_force_k5_partial, locs_to_mask, Block, HeysCipher,
+};
+use cryptanalysis::io::{read_blocks_binstr, write_blocks_binstr};
+use std::error::Error;
+
+/// Rank last-round key candidates of Heys' cipher using a linear approximation
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    rank: RankArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write plaintext and ciphertext files for a cipher with seeded keys
+    Generate(GenerateArgs),
+
+    /// Print the bias of the linear approximation under a single K5 guess
+    Bias(BiasArgs),
+}
+
+#[derive(Args, Debug)]
+struct RankArgs {
+    /// File with one 16-bit binary string plaintext per line
+    #[arg(long, required = true)]
+    plaintexts: Option<String>,
+
+    /// File with one 16-bit binary string ciphertext per line
+    #[arg(long, required = true)]
+    ciphertexts: Option<String>,
+
+    /// Comma-separated plaintext bit locs (big-endian, 1-based)
+    #[arg(long, value_delimiter = ',', default_values_t = [5, 7, 8])]
+    pt_locs: Vec<u8>,
+
+    /// Comma-separated bit locs of the state entering the last S-box layer
+    #[arg(long, value_delimiter = ',', default_values_t = [6, 8, 14, 16])]
+    u4_locs: Vec<u8>,
+
+    /// Number of candidates to print
+    #[arg(long, default_value_t = 5)]
+    top: usize,
+
+    /// Only enumerate the K5 bits under the S-boxes the U4 locs touch,
+    /// leaving the other bits zero
+    #[arg(long)]
+    partial: bool,
+}
+
+#[derive(Args, Debug)]
+struct BiasArgs {
+    /// File with one 16-bit binary string plaintext per line
+    #[arg(long)]
+    plaintexts: String,
+
+    /// File with one 16-bit binary string ciphertext per line
+    #[arg(long)]
+    ciphertexts: String,
+
+    /// The K5 guess, decimal or 0x-prefixed hex
+    #[arg(long, value_parser = parse_key)]
+    k5: u16,
+
+    /// Comma-separated plaintext bit locs (big-endian, 1-based)
+    #[arg(long, value_delimiter = ',', default_values_t = [5, 7, 8])]
+    pt_locs: Vec<u8>,
+
+    /// Comma-separated bit locs of the state entering the last S-box layer
+    #[arg(long, value_delimiter = ',', default_values_t = [6, 8, 14, 16])]
+    u4_locs: Vec<u8>,
+}
+
+#[derive(Args, Debug)]
+struct GenerateArgs {
+    /// Seed for the round keys and the random plaintexts
+    #[arg(long)]
+    seed: u64,
+
+    /// Number of plaintext-ciphertext pairs
+    #[arg(long)]
+    count: usize,
+
+    #[arg(long)]
+    out_plaintexts: String,
+
+    #[arg(long)]
+    out_ciphertexts: String,
+
+    /// Comma-separated round keys K1 to K5, decimal or 0x-prefixed hex,
+    /// instead of drawing them from the seed
+    #[arg(long, value_delimiter = ',', value_parser = parse_key)]
+    keys: Option<Vec<u16>>,
+}
+
+fn parse_key(key: &str) -> Result<u16, String> {
+    let parsed = match key.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => key.parse::<u16>(),
+    };
+    return parsed.map_err(|err| format!("invalid round key {key:?}: {err}"));
+}
+
+fn rank(args: RankArgs) -> Result<(), Box<dyn Error>> {
+    // Only optional so that the generate subcommand can go without them
+    let plaintexts = read_blocks_binstr(&args.plaintexts.ok_or("missing --plaintexts")?)?;
+    let ciphertexts = read_blocks_binstr(&args.ciphertexts.ok_or("missing --ciphertexts")?)?;
+
+    let rankings = if args.partial {
+        let active = active_sbox_mask(locs_to_mask(&args.u4_locs)?);
+        brute_force_k5_partial(
+            &plaintexts,
+            &ciphertexts,
+            &args.pt_locs,
+            &args.u4_locs,
+            active,
+        )?
+    } else {
+        brute_force_k5(
+            &plaintexts,
+            &ciphertexts,
+            &args.pt_locs,
+            &args.u4_locs,
+            [0; 4],
+        )?
+    };
+    rankings
         .iter()
-        .map(|pt| cipher.encrypt(pt).unwrap())
-        .collect::<Vec<Block>>();
+        .take(args.top)
+        .for_each(|(bias, round_key)| {
+            println!("K5 candidate: 0x{round_key:04x}, observed bias: {bias:.6}");
+        });
+    return Ok(());
+}
+
+fn generate(args: GenerateArgs) -> Result<(), Box<dyn Error>> {
+    let cipher = match args.keys {
+        Some(keys) => {
+            if keys.len() != 5 {
+                return Err(format!("expected 5 round keys but got {}", keys.len()).into());
+            }
+            HeysCipher::from_keys(&keys)?
+        }
+        None => HeysCipher::random(args.seed),
+    };
+    // The plaintexts are drawn from the next seed, since the stream of `seed`
+    // itself starts with the round keys
+    let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) = cipher
+        .sample_pairs(args.count, args.seed.wrapping_add(1))?
+        .into_iter()
+        .unzip();
+
+    write_blocks_binstr(&args.out_plaintexts, &plaintexts)?;
+    write_blocks_binstr(&args.out_ciphertexts, &ciphertexts)?;
+    let round_keys = cipher
+        .round_keys()
+        .iter()
+        .map(|round_key| format!("0x{round_key:04x}"))
+        .collect::<Vec<String>>();
+    println!("Round keys: {}", round_keys.join(","));
+    return Ok(());
+}
+
+fn bias(args: BiasArgs) -> Result<(), Box<dyn Error>> {
+    let plaintexts = read_blocks_binstr(&args.plaintexts)?;
+    let ciphertexts = read_blocks_binstr(&args.ciphertexts)?;
+    // Only the last round key is used to peel off the last round
+    let guess = HeysCipher::from_keys(&[0, 0, 0, 0, args.k5])?;
+    let bias = guess.get_bias(&plaintexts, &ciphertexts, &args.pt_locs, &args.u4_locs)?;
+    println!("bias: {bias:.08}");
+    return Ok(());
+}
 
-    let bias = cipher.get_bias(&plaintexts, &ciphertexts, &[5, 7, 8], &[6, 8, 14, 16]);
-    println!("{bias}");
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    return match cli.command {
+        Some(Command::Generate(args)) => generate(args),
+        Some(Command::Bias(args)) => bias(args),
+        None => rank(cli.rank),
+    };
 }
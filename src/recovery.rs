@@ -0,0 +1,247 @@
+//! Recovery of all five round keys of Heys' cipher by attacking the last
+//! round, peeling it off, and repeating on the shorter cipher
+use crate::error::{Error, Result};
+use crate::heys::{
+    active_sbox_mask, partial_key_biases, sort_rankings, validate_pairs, Block, PERMUTATION,
+    PERMUTATION_INVERT, SBOX_INVERT,
+};
+use crate::trail::MultiRoundTrail;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// How many of the best-scoring keys of each stage are tried before giving up
+const CANDIDATES_PER_STAGE: usize = 4;
+
+/// Recover K1 to K5 from PT-CT pairs of the 4-round Heys cipher.
+///
+/// The 3-round trails rank K5, after which the last round is peeled off and
+/// the 2-round trails rank K4 of the remaining 3-round cipher, then the
+/// 1-round trails rank K3. Within each stage every S-box of the last round
+/// must be activated by at least one trail, and candidates are scored by the
+/// sum of their squared biases over the stage's trails. The last two keys need
+/// no trail: only the right K2 makes `PT ^ K1` consistent across all pairs.
+///
+/// Keys that differ only in bits no trail can tell apart score the same, so
+/// the best few candidates of each stage are tried depth-first and the first
+/// combination that passes the K1/K2 consistency check is returned.
+///
+/// Between stages the permutation is moved onto the key: a round that ends
+/// in `P(x) ^ K` is attacked as `x ^ P^-1(K)`, so every stage has the same
+/// shape as the last round of the full cipher.
+pub fn recover_all_keys(
+    plaintexts: &[Block],
+    ciphertexts: &[Block],
+    trails: &[MultiRoundTrail],
+) -> Result<[u16; 5]> {
+    validate_pairs(plaintexts, ciphertexts)?;
+    if let Some(trail) = trails
+        .iter()
+        .find(|trail| !(1..=3).contains(&trail.rounds.len()))
+    {
+        return Err(Error::UnsupportedTrail {
+            rounds: trail.rounds.len(),
+        });
+    }
+    let stage_trails = (1..=3)
+        .rev()
+        .map(|rounds| {
+            let stage_trails = trails
+                .iter()
+                .filter(|trail| trail.rounds.len() == rounds)
+                .collect::<Vec<&MultiRoundTrail>>();
+            let covered = stage_trails
+                .iter()
+                .fold(0, |covered, trail| covered | active_sbox_mask(trail.u_mask));
+            if let Some(sbox) = (0..4).find(|sbox| (covered >> (12 - 4 * sbox)) & 0xf == 0) {
+                return Err(Error::MissingTrail {
+                    rounds,
+                    sbox: sbox + 1,
+                });
+            }
+            return Ok(stage_trails);
+        })
+        .collect::<Result<Vec<Vec<&MultiRoundTrail>>>>()?;
+
+    let search = KeySearch {
+        plaintexts,
+        stage_trails,
+        sbox_invert: &SBOX_INVERT,
+        permutation_invert: &PERMUTATION_INVERT,
+    };
+    let mut last_keys = vec![];
+    if !search.run(ciphertexts, &mut last_keys)? {
+        return Err(Error::NoConsistentKeys);
+    }
+
+    // last_keys holds K5, then P^-1 of K4, K3, and K2, then K1
+    let mut round_keys = [0u16; 5];
+    round_keys[4] = last_keys[0];
+    for stage in 1..4 {
+        round_keys[4 - stage] = Block::new(last_keys[stage]).permute(&PERMUTATION)?.value();
+    }
+    round_keys[0] = last_keys[4];
+    return Ok(round_keys);
+}
+
+struct KeySearch<'a> {
+    plaintexts: &'a [Block],
+    /// The 3-round, 2-round, and 1-round trails
+    stage_trails: Vec<Vec<&'a MultiRoundTrail>>,
+    sbox_invert: &'static [u16; 16],
+    permutation_invert: &'static [usize; 16],
+}
+
+impl KeySearch<'_> {
+    /// Try the best candidates for the last key of `states`, peeling each off
+    /// and recursing. On success `last_keys` holds the recovered keys.
+    fn run(&self, states: &[Block], last_keys: &mut Vec<u16>) -> Result<bool> {
+        let stage = last_keys.len();
+        if stage == self.stage_trails.len() {
+            if let Some((k1, k2)) = self.solve_first_round(states) {
+                last_keys.push(k2);
+                last_keys.push(k1);
+                return Ok(true);
+            }
+            return Ok(false);
+        }
+        for last_key in self.rank_last_keys(states, &self.stage_trails[stage]) {
+            let peeled = states
+                .iter()
+                .map(|state| {
+                    return state
+                        .mix_key(last_key)
+                        .substitute(self.sbox_invert)
+                        .permute(self.permutation_invert);
+                })
+                .collect::<Result<Vec<Block>>>()?;
+            last_keys.push(last_key);
+            if self.run(&peeled, last_keys)? {
+                return Ok(true);
+            }
+            last_keys.pop();
+        }
+        return Ok(false);
+    }
+
+    /// The best keys mixed in after the last S-box layer of `states`, by the
+    /// sum of squared biases over the trails
+    fn rank_last_keys(&self, states: &[Block], trails: &[&MultiRoundTrail]) -> Vec<u16> {
+        let mut scores = vec![0.0; 1 << 16];
+        for trail in trails {
+            let active = active_sbox_mask(trail.u_mask) as usize;
+            let mut biases = vec![0.0; 1 << 16];
+            for (bias, partial_key) in partial_key_biases(
+                self.plaintexts,
+                states,
+                trail.pt_mask,
+                trail.u_mask,
+                self.sbox_invert,
+            ) {
+                biases[partial_key as usize] = bias;
+            }
+            for (key, score) in scores.iter_mut().enumerate() {
+                let bias: f64 = biases[key & active];
+                *score += bias * bias;
+            }
+        }
+        let mut rankings = scores
+            .into_iter()
+            .zip(0..=u16::MAX)
+            .collect::<Vec<(f64, u16)>>();
+        sort_rankings(&mut rankings);
+        return rankings
+            .into_iter()
+            .take(CANDIDATES_PER_STAGE)
+            .map(|(_, key)| key)
+            .collect::<Vec<u16>>();
+    }
+
+    /// Solve `state = S(PT ^ K1) ^ K2` one S-box at a time, returning
+    /// (K1, K2) if every S-box has exactly one consistent solution
+    fn solve_first_round(&self, states: &[Block]) -> Option<(u16, u16)> {
+        let mut k1 = 0;
+        let mut k2 = 0;
+        for shift in (0..16).step_by(4) {
+            let consistent = (0u16..16)
+                .filter_map(|k2_nibble| {
+                    let mut k1_nibbles =
+                        self.plaintexts
+                            .iter()
+                            .zip(states.iter())
+                            .map(|(pt, state)| {
+                                let state_nibble = (state.value() >> shift) & 0xf;
+                                let pt_nibble = (pt.value() >> shift) & 0xf;
+                                return self.sbox_invert[(state_nibble ^ k2_nibble) as usize]
+                                    ^ pt_nibble;
+                            });
+                    let first = k1_nibbles.next()?;
+                    if k1_nibbles.all(|k1_nibble| k1_nibble == first) {
+                        return Some((first, k2_nibble));
+                    }
+                    return None;
+                })
+                .collect::<Vec<(u16, u16)>>();
+            if consistent.len() != 1 {
+                return None;
+            }
+            let (k1_nibble, k2_nibble) = consistent[0];
+            k1 |= k1_nibble << shift;
+            k2 |= k2_nibble << shift;
+        }
+        return Some((k1, k2));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heys::{HeysCipher, SBOX};
+    use crate::lat::LinearApproximationTable;
+
+    /// Trails activating at most two S-boxes of the last round, none of them
+    /// through a U mask nibble for which some key difference cancels out
+    const STAGE_MASKS: [&[(u16, u16)]; 10] = [
+        &[(0x0066, 0x0033), (0x0033, 0x0099), (0x3003, 0x9009)],
+        &[(0x0a00, 0x0400), (0x0400, 0x0400), (0x0400, 0x0500)],
+        &[(0x0044, 0x0055), (0x0303, 0x0909), (0x5005, 0x6006)],
+        &[(0x0044, 0x0055), (0x0303, 0x0909), (0x5005, 0xa00a)],
+        &[(0x0033, 0x0099), (0x3003, 0x9009)],
+        &[(0x0a00, 0x0400), (0x0400, 0x0500)],
+        &[(0x0303, 0x0909), (0x5005, 0x6006)],
+        &[(0x0303, 0x0909), (0x5005, 0xa00a)],
+        &[(0x0300, 0x0900)],
+        &[(0x0500, 0x0600)],
+    ];
+
+    fn stage_trails() -> Vec<MultiRoundTrail> {
+        let lat = LinearApproximationTable::from_sbox(&SBOX);
+        return STAGE_MASKS
+            .iter()
+            .map(|masks| MultiRoundTrail::from_masks(&lat, &PERMUTATION, masks).unwrap())
+            .collect::<Vec<MultiRoundTrail>>();
+    }
+
+    #[test]
+    fn test_recover_all_keys() {
+        let round_keys = [0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e];
+        let cipher = HeysCipher::from_keys(&round_keys).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) =
+            cipher.encrypt_codebook().unwrap().into_iter().unzip();
+        let recovered = recover_all_keys(&plaintexts, &ciphertexts, &stage_trails()).unwrap();
+        assert_eq!(recovered, round_keys);
+    }
+
+    #[test]
+    fn test_recover_all_keys_missing_trail() {
+        let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+        let plaintexts = vec![Block::new(0x1234)];
+        let ciphertexts = cipher.encrypt_blocks(&plaintexts).unwrap();
+        let mut trails = stage_trails();
+        trails.retain(|trail| trail.rounds.len() != 2 || trail.u_mask & 0x0f00 == 0);
+        let err = recover_all_keys(&plaintexts, &ciphertexts, &trails).unwrap_err();
+        assert!(err.to_string().starts_with("No 2-round trail"), "{err}");
+        assert!(recover_all_keys(&[], &[], &trails).is_err());
+        let err = recover_all_keys(&plaintexts, &[], &trails).unwrap_err();
+        assert_eq!(err.to_string(), "1 plaintexts but 0 ciphertexts");
+    }
+}
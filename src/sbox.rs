@@ -0,0 +1,112 @@
+//! A one-call summary of the linear and differential properties of a 4-bit
+//! S-box
+use crate::ddt::DifferenceDistributionTable;
+use crate::lat::LinearApproximationTable;
+use crate::spn::validate_sbox;
+use core::fmt;
+
+/// The tables and metrics of `sbox_report`. `Display` prints the metrics
+/// followed by the LAT, as `N_L(a, b) - 8` like Heys' table, and the DDT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SboxReport {
+    pub sbox: [u16; 16],
+    pub lat: LinearApproximationTable,
+    pub ddt: DifferenceDistributionTable,
+    pub linearity: u32,
+    pub nonlinearity: u32,
+    pub differential_uniformity: u16,
+    pub is_bijection: bool,
+}
+
+/// Compute the LAT, the DDT, and the metrics derived from them
+pub fn sbox_report(sbox: &[u16; 16]) -> SboxReport {
+    let lat = LinearApproximationTable::from_sbox(sbox);
+    let ddt = DifferenceDistributionTable::from_sbox(sbox);
+    return SboxReport {
+        sbox: *sbox,
+        linearity: lat.linearity(),
+        nonlinearity: lat.nonlinearity(),
+        differential_uniformity: ddt.differential_uniformity(),
+        is_bijection: validate_sbox(sbox).is_ok(),
+        lat,
+        ddt,
+    };
+}
+
+/// One 16 by 16 table with hex row and column headers
+fn write_table(
+    f: &mut fmt::Formatter<'_>,
+    title: &str,
+    entry: impl Fn(u16, u16) -> i32,
+) -> fmt::Result {
+    writeln!(f, "{title}")?;
+    write!(f, "   ")?;
+    for col in 0..16 {
+        write!(f, " {col:>2X}")?;
+    }
+    writeln!(f)?;
+    for row in 0..16 {
+        write!(f, "{row:>2X} ")?;
+        for col in 0..16 {
+            write!(f, " {:>2}", entry(row, col))?;
+        }
+        writeln!(f)?;
+    }
+    return Ok(());
+}
+
+impl fmt::Display for SboxReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "S-box:")?;
+        for output in self.sbox {
+            write!(f, " {output:X}")?;
+        }
+        writeln!(f)?;
+        let bijection = if self.is_bijection { "yes" } else { "no" };
+        writeln!(f, "Bijection: {bijection}")?;
+        writeln!(f, "Linearity: {}", self.linearity)?;
+        writeln!(f, "Nonlinearity: {}", self.nonlinearity)?;
+        writeln!(
+            f,
+            "Differential uniformity: {}",
+            self.differential_uniformity
+        )?;
+        writeln!(f)?;
+        write_table(f, "LAT (N_L - 8)", |input_mask, output_mask| {
+            return self.lat.count(input_mask, output_mask) as i32 - 8;
+        })?;
+        writeln!(f)?;
+        return write_table(f, "DDT", |input_diff, output_diff| {
+            return self.ddt.count(input_diff, output_diff) as i32;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heys::SBOX;
+
+    #[test]
+    fn test_sbox_report() {
+        let report = sbox_report(&SBOX);
+        assert!(report.is_bijection);
+        assert_eq!(report.linearity, 12);
+        assert_eq!(report.nonlinearity, 2);
+        assert_eq!(report.differential_uniformity, 8);
+        assert_eq!(report.lat, LinearApproximationTable::from_sbox(&SBOX));
+        assert_eq!(report.ddt.count(0xb, 0x2), 8);
+
+        let printed = report.to_string();
+        assert!(printed.starts_with("S-box: E 4 D 1 2 F B 8 3 A 6 C 5 9 0 7\n"));
+        assert!(printed.contains("Bijection: yes\n"));
+        assert!(printed.contains("Differential uniformity: 8\n"));
+        // Row B of the LAT and the DDT, matching the tables in Heys' tutorial
+        assert!(printed.contains("\n B   0  4  0 -4  4  0  4  0  0  0  0  0  0  0  0  0\n"));
+        assert!(printed.contains("\n B   0  0  8  0  0  2  0  2  0  0  0  0  0  2  0  2\n"));
+
+        let mut repeated = SBOX;
+        repeated[1] = repeated[0];
+        assert!(!sbox_report(&repeated).is_bijection);
+    }
+}
@@ -0,0 +1,459 @@
+//! A generic substitution-permutation network over 16-bit blocks
+use crate::error::{Error, Result};
+use crate::heys::Block;
+use alloc::vec::Vec;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// A substitution-permutation network with a 4-bit S-box and a bit
+/// permutation
+///
+/// A cipher with N rounds has N + 1 round keys. Each of the first N - 1 rounds
+/// mixes in a round key, substitutes, and permutes; the last round mixes in a
+/// round key and substitutes but skips the permutation, after which the final
+/// round key is mixed in.
+#[derive(Debug, Clone)]
+pub struct SpnCipher {
+    rounds: usize,
+    round_keys: Vec<u16>,
+    sbox: [u16; 16],
+    sbox_invert: [u16; 16],
+    permutation: [usize; 16],
+    permutation_invert: [usize; 16],
+}
+
+/// Check that the S-box is a bijection on 0..16: every output is below 16
+/// and no two inputs share an output
+pub fn validate_sbox(sbox: &[u16; 16]) -> Result<()> {
+    let mut seen = [false; 16];
+    for (input, output) in sbox.iter().enumerate() {
+        if *output >= 16 {
+            return Err(Error::SboxOutputOutOfRange {
+                input: input as u16,
+                output: *output,
+            });
+        }
+        if seen[*output as usize] {
+            return Err(Error::SboxNotBijective { output: *output });
+        }
+        seen[*output as usize] = true;
+    }
+    return Ok(());
+}
+
+/// Inverse of the S-box, indexed by the output nibble. The S-box must pass
+/// `validate_sbox`; otherwise the result is not an inverse.
+pub fn invert_sbox(sbox: &[u16; 16]) -> [u16; 16] {
+    let mut inverse = [0; 16];
+    for (input, output) in sbox.iter().enumerate() {
+        inverse[(*output % 16) as usize] = input as u16;
+    }
+    return inverse;
+}
+
+/// Check that the permutation is a bijection on the 16 bit shifts, so that
+/// every single-bit mask maps to a distinct single-bit mask
+pub fn validate_permutation(permutation: &[usize; 16]) -> Result<()> {
+    let mut seen = [false; 16];
+    for (from, to) in permutation.iter().enumerate() {
+        if *to >= 16 {
+            return Err(Error::PermutationOutOfRange {
+                from,
+                to: *to,
+                bits: 16,
+            });
+        }
+        if seen[*to] {
+            return Err(Error::PermutationNotBijective { to: *to });
+        }
+        seen[*to] = true;
+    }
+    return Ok(());
+}
+
+/// Inverse of the permutation. The permutation must pass
+/// `validate_permutation`; otherwise the result is not an inverse.
+pub fn invert_permutation(permutation: &[usize; 16]) -> [usize; 16] {
+    let mut inverse = [0; 16];
+    for (from, to) in permutation.iter().enumerate() {
+        inverse[to % 16] = from;
+    }
+    return inverse;
+}
+
+impl SpnCipher {
+    /// Cipher with `rounds` rounds, which needs `rounds + 1` round keys. The
+    /// inverses of the S-box and permutation are derived automatically, so
+    /// both must be bijections.
+    pub fn new(
+        rounds: usize,
+        sbox: &[u16; 16],
+        permutation: &[usize; 16],
+        round_keys: &[u16],
+    ) -> Result<Self> {
+        if rounds == 0 {
+            return Err(Error::NoRounds);
+        }
+        if round_keys.len() != rounds + 1 {
+            return Err(Error::RoundKeyCount {
+                rounds,
+                keys: round_keys.len(),
+            });
+        }
+        validate_sbox(sbox)?;
+        validate_permutation(permutation)?;
+        return Ok(Self {
+            rounds,
+            round_keys: round_keys.to_vec(),
+            sbox: *sbox,
+            sbox_invert: invert_sbox(sbox),
+            permutation: *permutation,
+            permutation_invert: invert_permutation(permutation),
+        });
+    }
+
+    pub fn num_rounds(&self) -> usize {
+        return self.rounds;
+    }
+
+    pub fn round_keys(&self) -> &[u16] {
+        return &self.round_keys;
+    }
+
+    pub fn sbox(&self) -> &[u16; 16] {
+        return &self.sbox;
+    }
+
+    pub fn sbox_invert(&self) -> &[u16; 16] {
+        return &self.sbox_invert;
+    }
+
+    pub fn permutation(&self) -> &[usize; 16] {
+        return &self.permutation;
+    }
+
+    pub fn permutation_invert(&self) -> &[usize; 16] {
+        return &self.permutation_invert;
+    }
+
+    pub fn encrypt(&self, plaintext: &Block) -> Result<Block> {
+        return self.encrypt_rounds(plaintext, self.rounds);
+    }
+
+    /// The state after the first `rounds` rounds, each of which mixes in its
+    /// round key, substitutes, and permutes. The last round mixes in the final
+    /// round key instead of permuting, so after every round this is the
+    /// ciphertext, and after none it is the plaintext.
+    pub fn encrypt_rounds(&self, plaintext: &Block, rounds: usize) -> Result<Block> {
+        if rounds > self.rounds {
+            return Err(Error::TooManyRounds {
+                rounds,
+                cipher_rounds: self.rounds,
+            });
+        }
+        let mut state = *plaintext;
+        for round in 0..rounds {
+            state = state.mix_key(self.round_keys[round]).substitute(&self.sbox);
+            if round + 1 < self.rounds {
+                state = state.permute(&self.permutation)?;
+            } else {
+                state = state.mix_key(self.round_keys[round + 1]);
+            }
+        }
+        return Ok(state);
+    }
+
+    pub fn decrypt(&self, ciphertext: &Block) -> Result<Block> {
+        return self.decrypt_rounds(ciphertext, self.rounds);
+    }
+
+    /// Undo the last `rounds` rounds, giving the state that `encrypt_rounds`
+    /// reaches after the other `num_rounds() - rounds` rounds. Peeling one
+    /// round is `peel_last_round` followed by mixing in the last round's key.
+    pub fn decrypt_rounds(&self, ciphertext: &Block, rounds: usize) -> Result<Block> {
+        if rounds > self.rounds {
+            return Err(Error::TooManyRounds {
+                rounds,
+                cipher_rounds: self.rounds,
+            });
+        }
+        let mut state = *ciphertext;
+        for round in (self.rounds - rounds..self.rounds).rev() {
+            if round + 1 < self.rounds {
+                state = state.permute(&self.permutation_invert)?;
+            } else {
+                state = state.mix_key(self.round_keys[round + 1]);
+            }
+            state = state
+                .substitute(&self.sbox_invert)
+                .mix_key(self.round_keys[round]);
+        }
+        return Ok(state);
+    }
+
+    /// Undo the final key mixing and the last round's substitution, giving
+    /// the state entering the last S-box layer
+    pub fn peel_last_round(&self, ciphertext: &Block) -> Block {
+        return ciphertext
+            .mix_key(self.round_keys[self.round_keys.len() - 1])
+            .substitute(&self.sbox_invert);
+    }
+
+    /// Encrypt every block, stopping at the first error
+    pub fn encrypt_blocks(&self, blocks: &[Block]) -> Result<Vec<Block>> {
+        return map_blocks(blocks, |block| self.encrypt(block));
+    }
+
+    /// Decrypt every block, stopping at the first error
+    pub fn decrypt_blocks(&self, blocks: &[Block]) -> Result<Vec<Block>> {
+        return map_blocks(blocks, |block| self.decrypt(block));
+    }
+
+    /// Encrypt a multi-block message in CBC mode: each plaintext block is
+    /// mixed with the previous ciphertext block, starting from `iv`, before
+    /// it is encrypted. This only exists to generate more realistic datasets;
+    /// a 16-bit block cipher offers no real security in any mode.
+    pub fn encrypt_cbc(&self, iv: Block, blocks: &[Block]) -> Result<Vec<Block>> {
+        let mut previous = iv;
+        let mut ciphertexts = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            previous = self.encrypt(&block.mix_key(previous.value()))?;
+            ciphertexts.push(previous);
+        }
+        return Ok(ciphertexts);
+    }
+
+    /// Inverse of `encrypt_cbc` under the same `iv`
+    pub fn decrypt_cbc(&self, iv: Block, blocks: &[Block]) -> Result<Vec<Block>> {
+        let mut previous = iv;
+        let mut plaintexts = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            plaintexts.push(self.decrypt(block)?.mix_key(previous.value()));
+            previous = *block;
+        }
+        return Ok(plaintexts);
+    }
+
+    /// Double encryption: this cipher followed by `other`, e.g. for studying
+    /// meet-in-the-middle attacks
+    pub fn then(&self, other: &SpnCipher) -> ComposedCipher {
+        return ComposedCipher {
+            first: self.clone(),
+            second: other.clone(),
+        };
+    }
+}
+
+/// Two ciphers chained so that the ciphertext of the first is the plaintext
+/// of the second
+#[derive(Debug, Clone)]
+pub struct ComposedCipher {
+    first: SpnCipher,
+    second: SpnCipher,
+}
+
+impl ComposedCipher {
+    pub fn new(first: SpnCipher, second: SpnCipher) -> Self {
+        return Self { first, second };
+    }
+
+    pub fn first(&self) -> &SpnCipher {
+        return &self.first;
+    }
+
+    pub fn second(&self) -> &SpnCipher {
+        return &self.second;
+    }
+
+    pub fn encrypt(&self, plaintext: &Block) -> Result<Block> {
+        let middle = self.first.encrypt(plaintext)?;
+        return self.second.encrypt(&middle);
+    }
+
+    pub fn decrypt(&self, ciphertext: &Block) -> Result<Block> {
+        let middle = self.second.decrypt(ciphertext)?;
+        return self.first.decrypt(&middle);
+    }
+}
+
+/// Apply a fallible block operation to every block, in parallel if enabled
+fn map_blocks<F>(blocks: &[Block], op: F) -> Result<Vec<Block>>
+where
+    F: Fn(&Block) -> Result<Block> + Sync,
+{
+    #[cfg(feature = "parallel")]
+    let output = blocks.par_iter().map(&op).collect::<Result<Vec<Block>>>()?;
+    #[cfg(not(feature = "parallel"))]
+    let output = blocks.iter().map(op).collect::<Result<Vec<Block>>>()?;
+    return Ok(output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heys::{HeysCipher, PERMUTATION, PERMUTATION_INVERT, SBOX};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_heys_preset() {
+        let round_keys = [0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e];
+        let spn = SpnCipher::new(4, &SBOX, &PERMUTATION, &round_keys).unwrap();
+        let heys = HeysCipher::from_keys(&round_keys).unwrap();
+        assert_eq!(spn.num_rounds(), 4);
+        assert_eq!(heys.num_rounds(), 4);
+        for val in (0x0000u16..=0xffff).step_by(7) {
+            let pt = Block::new(val);
+            assert_eq!(spn.encrypt(&pt).unwrap(), heys.encrypt(&pt).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_new_validation() {
+        assert!(SpnCipher::new(0, &SBOX, &PERMUTATION, &[1]).is_err());
+        let err = SpnCipher::new(4, &SBOX, &PERMUTATION, &[1, 2, 3, 4]).unwrap_err();
+        assert_eq!(err.to_string(), "4 rounds require 5 round keys but got 4");
+        let spn = SpnCipher::new(1, &SBOX, &PERMUTATION, &[1, 2]).unwrap();
+        let pt = Block::new(0xbeef);
+        assert_eq!(spn.decrypt(&spn.encrypt(&pt).unwrap()).unwrap(), pt);
+    }
+
+    #[test]
+    fn test_validate_sbox() {
+        assert!(validate_sbox(&SBOX).is_ok());
+        let inverse = invert_sbox(&SBOX);
+        assert!(validate_sbox(&inverse).is_ok());
+        for x in 0..16 {
+            assert_eq!(inverse[SBOX[x] as usize], x as u16);
+        }
+        assert_eq!(invert_sbox(&inverse), SBOX);
+
+        let mut repeated = SBOX;
+        repeated[1] = repeated[0];
+        let err = validate_sbox(&repeated).unwrap_err();
+        assert_eq!(err.to_string(), "S-box maps more than one input to 14");
+        let mut too_wide = SBOX;
+        too_wide[3] = 16;
+        let err = validate_sbox(&too_wide).unwrap_err();
+        assert_eq!(err.to_string(), "S-box maps 3 to 16, which is not below 16");
+        assert!(SpnCipher::new(1, &repeated, &PERMUTATION, &[1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_validate_permutation() {
+        assert!(validate_permutation(&PERMUTATION).is_ok());
+        assert_eq!(invert_permutation(&PERMUTATION), PERMUTATION_INVERT);
+        let rotate = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 0];
+        let inverse = invert_permutation(&rotate);
+        assert_eq!(
+            inverse,
+            [15, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14]
+        );
+        assert_eq!(invert_permutation(&inverse), rotate);
+
+        let mut repeated = PERMUTATION;
+        repeated[1] = repeated[0];
+        let err = validate_permutation(&repeated).unwrap_err();
+        assert_eq!(err.to_string(), "Permutation maps more than one shift to 0");
+        let mut too_wide = PERMUTATION;
+        too_wide[2] = 16;
+        assert!(validate_permutation(&too_wide).is_err());
+        assert!(SpnCipher::new(1, &SBOX, &repeated, &[1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_rounds() {
+        let heys = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let pt = Block::new(0x2468);
+        assert_eq!(heys.encrypt_rounds(&pt, 0).unwrap(), pt);
+        assert_eq!(
+            heys.encrypt_rounds(&pt, heys.num_rounds()).unwrap(),
+            heys.encrypt(&pt).unwrap()
+        );
+        // One round of the full cipher is the first round of a longer cipher
+        let one_round = heys.encrypt_rounds(&pt, 1).unwrap();
+        let expected = pt.mix_key(0x1234).substitute(&SBOX).permute(&PERMUTATION);
+        assert_eq!(one_round, expected.unwrap());
+        // Mixing in K2 gives the state entering the S-boxes of round 2
+        let reduced = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc]).unwrap();
+        assert_eq!(
+            reduced.peel_last_round(&reduced.encrypt(&pt).unwrap()),
+            heys.encrypt_rounds(&pt, 1).unwrap().mix_key(0x5678)
+        );
+        assert!(heys.encrypt_rounds(&pt, 5).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_rounds() {
+        let heys = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let pt = Block::new(0x2468);
+        let ct = heys.encrypt(&pt).unwrap();
+        for rounds in 0..=heys.num_rounds() {
+            let state = heys.encrypt_rounds(&pt, rounds).unwrap();
+            let peeled = heys
+                .decrypt_rounds(&ct, heys.num_rounds() - rounds)
+                .unwrap();
+            assert_eq!(peeled, state, "after {rounds} rounds");
+        }
+        assert_eq!(heys.decrypt_rounds(&ct, heys.num_rounds()).unwrap(), pt);
+        assert_eq!(heys.decrypt_rounds(&ct, 0).unwrap(), ct);
+        assert_eq!(
+            heys.decrypt_rounds(&ct, 1).unwrap(),
+            heys.peel_last_round(&ct).mix_key(0xdef0)
+        );
+        assert!(heys.decrypt_rounds(&ct, 5).is_err());
+    }
+
+    #[test]
+    fn test_composed_cipher() {
+        let first = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let second = HeysCipher::from_keys(&[0xa5a5, 0x3c3c, 0x0ff0]).unwrap();
+        let composed = first.then(&second);
+        for val in (0..=u16::MAX).step_by(257) {
+            let pt = Block::new(val);
+            let ct = composed.encrypt(&pt).unwrap();
+            assert_eq!(ct, second.encrypt(&first.encrypt(&pt).unwrap()).unwrap());
+            assert_eq!(composed.decrypt(&ct).unwrap(), pt);
+        }
+        let reversed = ComposedCipher::new(second.clone(), first.clone());
+        assert_eq!(reversed.first().round_keys(), second.round_keys());
+        assert_ne!(
+            reversed.encrypt(&Block::new(1)).unwrap(),
+            composed.encrypt(&Block::new(1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_peel_last_round() {
+        let spn = SpnCipher::new(1, &SBOX, &PERMUTATION, &[0, 0x00ff]).unwrap();
+        // With K1 = 0 the peeled state is the plaintext itself
+        let pt = Block::new(0x1234);
+        let ct = spn.encrypt(&pt).unwrap();
+        assert_eq!(spn.peel_last_round(&ct), pt);
+    }
+
+    #[test]
+    fn test_cbc_round_trip() {
+        let heys = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        for seed in 0..8 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let iv = Block::new(rng.gen());
+            let message = (0..rng.gen_range(2..=10))
+                .map(|_| Block::new(rng.gen()))
+                .collect::<Vec<Block>>();
+            let ciphertexts = heys.encrypt_cbc(iv, &message).unwrap();
+            assert_eq!(ciphertexts.len(), message.len());
+            assert_eq!(
+                ciphertexts[0],
+                heys.encrypt(&message[0].mix_key(iv.value())).unwrap()
+            );
+            assert_eq!(heys.decrypt_cbc(iv, &ciphertexts).unwrap(), message);
+
+            // Repeated plaintext blocks must not give repeated ciphertext blocks
+            let repeated = [0x6865, 0x7973, 0x7973, 0x7973, 0x0000].map(Block::new);
+            let ciphertexts = heys.encrypt_cbc(iv, &repeated).unwrap();
+            assert_ne!(ciphertexts[1], ciphertexts[2]);
+            assert_eq!(heys.decrypt_cbc(iv, &ciphertexts).unwrap(), repeated);
+        }
+        assert!(heys.encrypt_cbc(Block::new(0), &[]).unwrap().is_empty());
+    }
+}
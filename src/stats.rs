@@ -0,0 +1,198 @@
+//! Statistical estimates for linear attacks. Everything but `piling_up`
+//! needs floating-point functions from std and is only built with the `std`
+//! feature.
+
+/// The constant `c` in `N = c / bias^2` that Heys uses for his attack
+pub const DEFAULT_PLAINTEXT_CONSTANT: f64 = 8.0;
+
+#[cfg(feature = "std")]
+/// Matsui's rule of thumb for the number of known plaintexts `N = c / bias^2`
+/// needed to recover a subkey with good success probability, rounded up.
+/// Returns `usize::MAX` when the bias is zero.
+pub fn required_plaintexts(bias: f64, constant: f64) -> usize {
+    if bias == 0.0 {
+        return usize::MAX;
+    }
+    return (constant / (bias * bias)).ceil() as usize;
+}
+
+/// Bias of the XOR of independent approximations by the piling-up lemma,
+/// `2^(n - 1) * (bias_1 * bias_2 * ... * bias_n)`. A single bias is returned
+/// unchanged, and an empty slice gives 1/2: XORing no approximations leaves
+/// the relation `0 = 0`, which always holds.
+pub fn piling_up(biases: &[f64]) -> f64 {
+    // Scaling each factor by 2 is exact, so this is 2^(n - 1) times the
+    // product without calling powi, which is not available in core
+    return biases.iter().fold(0.5, |total, bias| total * (2.0 * bias));
+}
+
+#[cfg(feature = "std")]
+/// Standard normal CDF using the rational approximation 26.2.17 from
+/// Abramowitz and Stegun, accurate to within 7.5e-8
+pub fn normal_cdf(x: f64) -> f64 {
+    if x < 0.0 {
+        return 1.0 - normal_cdf(-x);
+    }
+    let t = 1.0 / (1.0 + 0.2316419 * x);
+    let poly = t
+        * (0.319381530
+            + t * (-0.356563782 + t * (1.781477937 + t * (-1.821255978 + t * 1.330274429))));
+    let density = (-x * x / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt();
+    return 1.0 - density * poly;
+}
+
+#[cfg(feature = "std")]
+/// Matsui's estimate of the probability that an attack with `n` known
+/// plaintexts ranks the correct key first, given the bias of the
+/// approximation: `Phi(2 * sqrt(n) * |bias|)`
+pub fn success_probability(bias: f64, n: usize) -> f64 {
+    return normal_cdf(2.0 * (n as f64).sqrt() * bias.abs());
+}
+
+#[cfg(feature = "std")]
+/// Inverse of `normal_cdf` for `p` in (0, 1), found by bisection
+fn normal_quantile(p: f64) -> f64 {
+    let (mut low, mut high) = (-40.0, 40.0);
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if normal_cdf(mid) < p {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    return (low + high) / 2.0;
+}
+
+#[cfg(feature = "std")]
+/// Wilson score interval for a probability `p` observed over `n` trials at
+/// the given two-sided `confidence`, as (lower, upper). Unlike the normal
+/// approximation it stays within [0, 1] and behaves well near 0 and 1.
+///
+/// Panics if `confidence` is not strictly between 0 and 1.
+pub fn wilson_interval(p: f64, n: usize, confidence: f64) -> (f64, f64) {
+    assert!(
+        confidence > 0.0 && confidence < 1.0,
+        "confidence must be between 0 and 1 but got {confidence}"
+    );
+    if n == 0 {
+        return (0.0, 1.0);
+    }
+    let n = n as f64;
+    let z = normal_quantile((1.0 + confidence) / 2.0);
+    let denominator = 1.0 + z * z / n;
+    let center = (p + z * z / (2.0 * n)) / denominator;
+    let half_width = z / denominator * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).sqrt();
+    return (center - half_width, center + half_width);
+}
+
+#[cfg(feature = "std")]
+/// Whether a bias observed over `n` PT-CT pairs is too large to be noise at
+/// the given two-sided `confidence`, such as 0.99. Over random pairs the
+/// observed bias is roughly normal with standard deviation `sqrt(1 / (4n))`,
+/// which is scaled by the normal quantile of the confidence to get the
+/// threshold. Nothing is distinguishable over zero pairs.
+///
+/// Panics if `confidence` is not strictly between 0 and 1.
+pub fn is_distinguishable(bias: f64, n: usize, confidence: f64) -> bool {
+    assert!(
+        confidence > 0.0 && confidence < 1.0,
+        "confidence must be between 0 and 1 but got {confidence}"
+    );
+    if n == 0 {
+        return false;
+    }
+    let threshold = normal_quantile((1.0 + confidence) / 2.0) * (1.0 / (4.0 * n as f64)).sqrt();
+    return bias.abs() > threshold;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_required_plaintexts() {
+        let bias = 1.0 / 32.0;
+        assert_eq!(required_plaintexts(bias, DEFAULT_PLAINTEXT_CONSTANT), 8192);
+        assert_eq!(required_plaintexts(-bias, DEFAULT_PLAINTEXT_CONSTANT), 8192);
+        assert_eq!(required_plaintexts(bias, 1.0), 1024);
+        assert_eq!(
+            required_plaintexts(0.0, DEFAULT_PLAINTEXT_CONSTANT),
+            usize::MAX
+        );
+    }
+
+    #[test]
+    fn test_piling_up() {
+        assert_eq!(piling_up(&[0.25, 0.25]), 0.125);
+        assert_eq!(piling_up(&[0.25, -0.25]), -0.125);
+        assert_eq!(piling_up(&[-0.375]), -0.375);
+        assert_eq!(piling_up(&[]), 0.5);
+        // Heys' 3-round trail: four S-boxes of bias 1/4 and -1/4
+        assert_eq!(piling_up(&[0.25, -0.25, -0.25, -0.25]), -1.0 / 32.0);
+        assert_eq!(piling_up(&[0.25, 0.0]), 0.0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_normal_cdf() {
+        assert!((normal_cdf(0.0) - 0.5).abs() < 1e-7);
+        assert!((normal_cdf(1.0) - 0.841345).abs() < 1e-6);
+        assert!((normal_cdf(1.96) - 0.975002).abs() < 1e-6);
+        assert!((normal_cdf(-1.0) - 0.158655).abs() < 1e-6);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_success_probability() {
+        // Matsui's table: N = k / bias^2 succeeds with 84.1%, 92.1%, 97.7%,
+        // and 99.8% for k = 1/4, 1/2, 1, and 2
+        let bias = 1.0 / 32.0;
+        assert!((success_probability(bias, 256) - 0.841).abs() < 1e-3);
+        assert!((success_probability(bias, 512) - 0.921).abs() < 1e-3);
+        assert!((success_probability(bias, 1024) - 0.977).abs() < 1e-3);
+        assert!((success_probability(-bias, 2048) - 0.998).abs() < 1e-3);
+        assert!((success_probability(0.0, 2048) - 0.5).abs() < 1e-7);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_normal_quantile() {
+        assert!(normal_quantile(0.5).abs() < 1e-6);
+        assert!((normal_quantile(0.975) - 1.96).abs() < 1e-3);
+        assert!((normal_quantile(0.025) + 1.96).abs() < 1e-3);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_wilson_interval() {
+        let (lower, upper) = wilson_interval(0.5, 100, 0.95);
+        assert!((lower - 0.4038).abs() < 1e-3);
+        assert!((upper - 0.5962).abs() < 1e-3);
+        // Stays within [0, 1] at the extremes
+        let (lower, upper) = wilson_interval(0.0, 10, 0.95);
+        assert!(lower.abs() < 1e-12 && upper > 0.0 && upper < 1.0);
+        assert_eq!(wilson_interval(0.3, 0, 0.95), (0.0, 1.0));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_is_distinguishable() {
+        // With a million pairs the noise is around 0.0005
+        assert!(!is_distinguishable(0.0001, 1_000_000, 0.99));
+        assert!(!is_distinguishable(-0.0001, 1_000_000, 0.99));
+        // Heys' 1/32 bias stands out clearly over 8192 pairs, but not over 16
+        assert!(is_distinguishable(1.0 / 32.0, 8192, 0.99));
+        assert!(is_distinguishable(-1.0 / 32.0, 8192, 0.99));
+        assert!(!is_distinguishable(1.0 / 32.0, 16, 0.99));
+        assert!(!is_distinguishable(0.5, 0, 0.99));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    #[should_panic]
+    fn test_is_distinguishable_bad_confidence() {
+        is_distinguishable(0.1, 100, 1.0);
+    }
+}
@@ -0,0 +1,544 @@
+//! Search for linear trails through the cipher's S-box layers
+use crate::error::{Error, Result};
+use crate::heys::{count_active_sboxes, HeysCipher};
+use crate::lat::LinearApproximationTable;
+use crate::stats::piling_up;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The linear approximation of one round's S-box layer. Masks cover the whole
+/// 16-bit state, and the bias combines all active S-boxes of the round with
+/// the piling-up lemma.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoundTrail {
+    pub input_mask: u16,
+    pub output_mask: u16,
+    pub bias: f64,
+}
+
+/// The `top` single-round trails with the highest bias magnitude. Each trail
+/// activates exactly one of the four S-boxes; ties are ordered by S-box (S1 is
+/// the most significant nibble), then by input mask and output mask.
+pub fn best_one_round_trails(lat: &LinearApproximationTable, top: usize) -> Vec<RoundTrail> {
+    let approximations = lat.best_approximations(15 * 15);
+    let mut trails = (0..4)
+        .flat_map(|sbox| {
+            let shift = 4 * (3 - sbox);
+            return approximations
+                .iter()
+                .map(move |(input_mask, output_mask, bias)| RoundTrail {
+                    input_mask: input_mask << shift,
+                    output_mask: output_mask << shift,
+                    bias: *bias,
+                });
+        })
+        .collect::<Vec<RoundTrail>>();
+    trails.sort_by(|trail1, trail2| {
+        return trail2.bias.abs().total_cmp(&trail1.bias.abs());
+    });
+    trails.truncate(top);
+    return trails;
+}
+
+/// A linear approximation over several rounds. `rounds[r]` approximates the
+/// S-box layer of round r + 1, and the output mask of each round passes
+/// through the permutation to become the input mask of the next.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiRoundTrail {
+    pub rounds: Vec<RoundTrail>,
+    /// Predicted bias of the whole trail from the piling-up lemma, assuming
+    /// zero round keys
+    pub bias: f64,
+    /// Mask over the plaintext bits
+    pub pt_mask: u16,
+    /// Mask over the state entering the S-box layer after the trail's last
+    /// round, e.g. U4 for a 3-round trail
+    pub u_mask: u16,
+}
+
+impl MultiRoundTrail {
+    fn from_rounds(rounds: Vec<RoundTrail>, permutation: &[usize; 16]) -> Self {
+        let bias = piling_up(&rounds.iter().map(|round| round.bias).collect::<Vec<f64>>());
+        let pt_mask = rounds[0].input_mask;
+        let u_mask = propagate_mask(rounds[rounds.len() - 1].output_mask, permutation);
+        return Self {
+            rounds,
+            bias,
+            pt_mask,
+            u_mask,
+        };
+    }
+
+    /// Trail through the given (input mask, output mask) of each round's
+    /// S-box layer, or None if there are no rounds
+    pub fn from_masks(
+        lat: &LinearApproximationTable,
+        permutation: &[usize; 16],
+        masks: &[(u16, u16)],
+    ) -> Option<Self> {
+        if masks.is_empty() {
+            return None;
+        }
+        let rounds = masks
+            .iter()
+            .map(|(input_mask, output_mask)| RoundTrail {
+                input_mask: *input_mask,
+                output_mask: *output_mask,
+                bias: round_bias(lat, *input_mask, *output_mask),
+            })
+            .collect::<Vec<RoundTrail>>();
+        return Some(Self::from_rounds(rounds, permutation));
+    }
+
+    /// Plaintext bit locs (big-endian, 1-based) for `HeysCipher::get_bias`
+    pub fn pt_locs(&self) -> Vec<u8> {
+        return mask_to_locs(self.pt_mask);
+    }
+
+    /// U4 bit locs (big-endian, 1-based) for `HeysCipher::get_bias`
+    pub fn u4_locs(&self) -> Vec<u8> {
+        return mask_to_locs(self.u_mask);
+    }
+}
+
+/// How the bias a trail predicts compares with the bias observed over the
+/// full codebook of a cipher
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiasComparison {
+    /// The trail's bias from the piling-up lemma
+    pub predicted: f64,
+    /// The signed bias observed over all 65536 plaintexts
+    pub observed: f64,
+    /// `|observed| - |predicted|`. The signs are left out because the round
+    /// keys flip the sign of the observed bias but not its magnitude.
+    pub difference: f64,
+}
+
+impl BiasComparison {
+    /// `|observed| / |predicted|`, which is 1 when the piling-up lemma holds
+    /// exactly
+    pub fn ratio(&self) -> f64 {
+        return self.observed.abs() / self.predicted.abs();
+    }
+}
+
+/// Compare the bias the trail predicts with the bias observed over the full
+/// codebook of `cipher`, which must have one more round than the trail so that
+/// the trail ends at the state entering the last S-box layer
+pub fn validate_trail(cipher: &HeysCipher, trail: &MultiRoundTrail) -> Result<BiasComparison> {
+    if cipher.num_rounds() != trail.rounds.len() + 1 {
+        return Err(Error::TrailLength {
+            trail_rounds: trail.rounds.len(),
+            cipher_rounds: cipher.num_rounds(),
+        });
+    }
+    let codebook = cipher.encrypt_codebook()?;
+    let holds = codebook
+        .iter()
+        .map(|(pt, ct)| {
+            cipher.check_linear_approx_mask(pt, ct, trail.pt_mask, trail.u_mask) as usize
+        })
+        .sum::<usize>();
+    let observed = (holds as f64) / (codebook.len() as f64) - 0.5;
+    return Ok(BiasComparison {
+        predicted: trail.bias,
+        observed,
+        difference: observed.abs() - trail.bias.abs(),
+    });
+}
+
+/// How useful the trail is for a last-round attack: `|bias| / 2^k`, where k
+/// is the number of S-boxes its U mask activates. A stronger bias needs fewer
+/// pairs, while every active S-box of the last round multiplies the partial
+/// keys to try by 16. The S-boxes inside the trail are not counted again since
+/// they already lower the bias through the piling-up lemma.
+pub fn trail_score(trail: &MultiRoundTrail) -> f64 {
+    let active = count_active_sboxes(trail.u_mask);
+    return trail.bias.abs() / f64::from(1u32 << active);
+}
+
+/// Big-endian, 1-based locs of the bits set in the mask
+fn mask_to_locs(mask: u16) -> Vec<u8> {
+    return (1u8..=16)
+        .filter(|loc| mask & (1 << (16 - loc)) != 0)
+        .collect::<Vec<u8>>();
+}
+
+/// Move each set bit of the mask through the permutation, where the bit at
+/// shift `i` is moved to shift `permutation[i]`. This turns the output mask of
+/// one S-box layer into the input mask of the next.
+pub fn propagate_mask(mask: u16, permutation: &[usize; 16]) -> u16 {
+    return permutation
+        .iter()
+        .enumerate()
+        .filter(|(shift, _)| (mask >> shift) & 1 != 0)
+        .map(|(_, mapped)| 1u16 << mapped)
+        .sum();
+}
+
+/// Undo `propagate_mask`: the bit at shift `permutation[i]` is moved back to
+/// shift `i`
+pub fn propagate_mask_inverse(mask: u16, permutation: &[usize; 16]) -> u16 {
+    return permutation
+        .iter()
+        .enumerate()
+        .filter(|(_, mapped)| (mask >> *mapped) & 1 != 0)
+        .map(|(shift, _)| 1u16 << shift)
+        .sum();
+}
+
+/// The bias of one round's S-box layer combined over its active S-boxes
+fn round_bias(lat: &LinearApproximationTable, input_mask: u16, output_mask: u16) -> f64 {
+    let biases = (0..4)
+        .map(|nibble| 4 * nibble)
+        .filter(|shift| (input_mask >> shift) & 0xf != 0)
+        .map(|shift| lat.bias(input_mask >> shift, output_mask >> shift))
+        .collect::<Vec<f64>>();
+    return piling_up(&biases);
+}
+
+/// Branch-and-bound search for the trail with the highest bias magnitude.
+///
+/// By the piling-up lemma a trail's bias magnitude is 1/2 times the product of
+/// `2 * |bias|` over its active S-boxes, so the search maximizes that product
+/// (the trail's weight) and prunes any partial trail that cannot beat the
+/// best weight found so far even if every remaining round had a single,
+/// strongest active S-box.
+struct TrailSearch<'a> {
+    lat: &'a LinearApproximationTable,
+    permutation: &'a [usize; 16],
+    rounds: usize,
+    /// For each S-box input mask, the output masks with non-zero bias,
+    /// strongest first
+    outputs: Vec<Vec<u16>>,
+    /// For each S-box output mask, the strongest input mask
+    best_inputs: Vec<u16>,
+    max_weight: f64,
+    best_weight: f64,
+    best_rounds: Vec<RoundTrail>,
+    current: Vec<RoundTrail>,
+}
+
+impl<'a> TrailSearch<'a> {
+    fn new(lat: &'a LinearApproximationTable, permutation: &'a [usize; 16], rounds: usize) -> Self {
+        let weight =
+            |input_mask: u16, output_mask: u16| 2.0 * lat.bias(input_mask, output_mask).abs();
+        let outputs = (0u16..16)
+            .map(|input_mask| {
+                let mut outputs = (1u16..16)
+                    .filter(|output_mask| weight(input_mask, *output_mask) > 0.0)
+                    .collect::<Vec<u16>>();
+                outputs.sort_by(|output1, output2| {
+                    return weight(input_mask, *output2).total_cmp(&weight(input_mask, *output1));
+                });
+                return outputs;
+            })
+            .collect::<Vec<Vec<u16>>>();
+        let best_inputs = (0u16..16)
+            .map(|output_mask| {
+                // max_by keeps the last maximum, so ties go to the smallest mask
+                return (1u16..16)
+                    .rev()
+                    .max_by(|input1, input2| {
+                        return weight(*input1, output_mask)
+                            .total_cmp(&weight(*input2, output_mask));
+                    })
+                    .unwrap();
+            })
+            .collect::<Vec<u16>>();
+        let max_weight = (1u16..16)
+            .flat_map(|input_mask| (1u16..16).map(move |output_mask| (input_mask, output_mask)))
+            .map(|(input_mask, output_mask)| weight(input_mask, output_mask))
+            .fold(0.0, f64::max);
+        return Self {
+            lat,
+            permutation,
+            rounds,
+            outputs,
+            best_inputs,
+            max_weight,
+            best_weight: 0.0,
+            best_rounds: vec![],
+            current: vec![],
+        };
+    }
+
+    fn weight(&self, input_mask: u16, output_mask: u16) -> f64 {
+        return 2.0 * self.lat.bias(input_mask, output_mask).abs();
+    }
+
+    /// Upper bound on the weight contributed by the rounds after `round`
+    fn remaining_bound(&self, round: usize) -> f64 {
+        return (round + 1..self.rounds).fold(1.0, |bound, _| bound * self.max_weight);
+    }
+
+    fn run(mut self) -> Option<MultiRoundTrail> {
+        // The first round's input mask is free, so enumerate its output masks
+        // and pick the strongest input for each active S-box
+        let mut first_rounds = (1u16..=u16::MAX)
+            .map(|output_mask| {
+                let mut input_mask = 0;
+                let mut weight = 1.0;
+                for shift in (0..4).map(|nibble| 4 * nibble) {
+                    let nibble = (output_mask >> shift) & 0xf;
+                    if nibble != 0 {
+                        let best_input = self.best_inputs[nibble as usize];
+                        input_mask |= best_input << shift;
+                        weight *= self.weight(best_input, nibble);
+                    }
+                }
+                return (weight, input_mask, output_mask);
+            })
+            .filter(|(weight, _, _)| *weight > 0.0)
+            .collect::<Vec<(f64, u16, u16)>>();
+        first_rounds.sort_by(|(weight1, _, _), (weight2, _, _)| weight2.total_cmp(weight1));
+
+        for (weight, input_mask, output_mask) in first_rounds {
+            if weight * self.remaining_bound(0) <= self.best_weight {
+                break;
+            }
+            self.push_round(input_mask, output_mask);
+            self.search_round(1, weight);
+            self.current.pop();
+        }
+
+        if self.best_rounds.is_empty() {
+            return None;
+        }
+        return Some(MultiRoundTrail::from_rounds(
+            self.best_rounds,
+            self.permutation,
+        ));
+    }
+
+    fn push_round(&mut self, input_mask: u16, output_mask: u16) {
+        self.current.push(RoundTrail {
+            input_mask,
+            output_mask,
+            bias: round_bias(self.lat, input_mask, output_mask),
+        });
+    }
+
+    /// Extend the current trail, whose weight so far is `weight`, with the
+    /// 0-based `round`
+    fn search_round(&mut self, round: usize, weight: f64) {
+        if round == self.rounds {
+            if weight > self.best_weight {
+                self.best_weight = weight;
+                self.best_rounds = self.current.clone();
+            }
+            return;
+        }
+        let previous = self.current[round - 1].output_mask;
+        let input_mask = propagate_mask(previous, self.permutation);
+        let nibbles = (0..4)
+            .map(|nibble| 4 * nibble)
+            .map(|shift| (shift, (input_mask >> shift) & 0xf))
+            .filter(|(_, nibble)| *nibble != 0)
+            .collect::<Vec<(u16, u16)>>();
+        self.choose_outputs(round, input_mask, &nibbles, 0, weight);
+    }
+
+    /// Choose an output mask for each of the remaining active S-boxes in
+    /// `nibbles`, given as (shift, input mask)
+    fn choose_outputs(
+        &mut self,
+        round: usize,
+        input_mask: u16,
+        nibbles: &[(u16, u16)],
+        output_mask: u16,
+        weight: f64,
+    ) {
+        if nibbles.is_empty() {
+            self.push_round(input_mask, output_mask);
+            self.search_round(round + 1, weight);
+            self.current.pop();
+            return;
+        }
+        let (shift, nibble) = nibbles[0];
+        let rest_bound = nibbles[1..]
+            .iter()
+            .map(|(_, other)| self.weight(*other, self.outputs[*other as usize][0]))
+            .product::<f64>();
+        let outputs = self.outputs[nibble as usize].clone();
+        for nibble_output in outputs {
+            let next_weight = weight * self.weight(nibble, nibble_output);
+            if next_weight * rest_bound * self.remaining_bound(round) <= self.best_weight {
+                // Outputs are sorted strongest first, so the rest are weaker
+                break;
+            }
+            self.choose_outputs(
+                round,
+                input_mask,
+                &nibbles[1..],
+                output_mask | (nibble_output << shift),
+                next_weight,
+            );
+        }
+    }
+}
+
+/// The trail over `rounds` S-box layers with the highest predicted bias
+/// magnitude, found by a branch-and-bound search over the LAT. Returns `None`
+/// when `rounds` is zero.
+///
+/// The trail's `pt_locs` and `u4_locs` can be fed directly to `get_bias` and
+/// `brute_force_k5`: a 3-round trail approximates the first three rounds of
+/// the 4-round cipher, ending at U4.
+pub fn build_multiround_trail(
+    lat: &LinearApproximationTable,
+    permutation: &[usize; 16],
+    rounds: usize,
+) -> Option<MultiRoundTrail> {
+    if rounds == 0 {
+        return None;
+    }
+    return TrailSearch::new(lat, permutation, rounds).run();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heys::{Block, HeysCipher, PERMUTATION, SBOX};
+
+    #[test]
+    fn test_propagate_mask() {
+        let mask = 0b1111000000000000;
+        let propagated = propagate_mask(mask, &PERMUTATION);
+        assert_eq!(propagated, 0b1000100010001000);
+        assert_eq!(propagate_mask_inverse(propagated, &PERMUTATION), mask);
+        assert_eq!(
+            Block::new(mask).permute(&PERMUTATION).unwrap().value(),
+            propagated
+        );
+
+        // A permutation that is not an involution rotates the bits left by one
+        let rotate = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 0];
+        assert_eq!(propagate_mask(0x8001, &rotate), 0x0003);
+        assert_eq!(propagate_mask_inverse(0x0003, &rotate), 0x8001);
+    }
+
+    #[test]
+    fn test_best_one_round_trails() {
+        let lat = LinearApproximationTable::from_sbox(&SBOX);
+        let trails = best_one_round_trails(&lat, 10);
+        assert_eq!(trails.len(), 10);
+        assert_eq!(trails[0].bias.abs(), 0.375);
+        assert!(trails
+            .windows(2)
+            .all(|pair| pair[0].bias.abs() >= pair[1].bias.abs()));
+        for trail in trails {
+            let active = (0..4)
+                .filter(|sbox| (trail.input_mask >> (4 * sbox)) & 0xf != 0)
+                .collect::<Vec<u16>>();
+            assert_eq!(active.len(), 1);
+            let shift = 4 * active[0];
+            let input_mask = trail.input_mask >> shift;
+            let output_mask = trail.output_mask >> shift;
+            assert_eq!(trail.bias, lat.bias(input_mask, output_mask));
+        }
+    }
+
+    #[test]
+    fn test_heys_trail() {
+        // Heys section 3.4: S12, S22, S32, and S34 give U4 bits 6, 8, 14, 16
+        let lat = LinearApproximationTable::from_sbox(&SBOX);
+        let masks = [(0x0b00, 0x0400), (0x0400, 0x0500), (0x0404, 0x0505)];
+        let trail = MultiRoundTrail::from_masks(&lat, &PERMUTATION, &masks).unwrap();
+        assert_eq!(trail.bias, -1.0 / 32.0);
+        assert_eq!(trail.pt_locs(), vec![5, 7, 8]);
+        assert_eq!(trail.u4_locs(), vec![6, 8, 14, 16]);
+        assert_eq!(
+            trail.rounds[2].bias,
+            lat.bias(0x4, 0x5) * lat.bias(0x4, 0x5) * 2.0
+        );
+        assert!(MultiRoundTrail::from_masks(&lat, &PERMUTATION, &[]).is_none());
+    }
+
+    #[test]
+    fn test_validate_trail() {
+        let lat = LinearApproximationTable::from_sbox(&SBOX);
+        let masks = [(0x0b00, 0x0400), (0x0400, 0x0500), (0x0404, 0x0505)];
+        let trail = MultiRoundTrail::from_masks(&lat, &PERMUTATION, &masks).unwrap();
+        let cipher = HeysCipher::from_keys(&[0x1234, 0x5678, 0x9abc, 0xdef0, 0x0f1e]).unwrap();
+        let comparison = validate_trail(&cipher, &trail).unwrap();
+        assert_eq!(comparison.predicted, -1.0 / 32.0);
+        assert_eq!(
+            comparison.difference,
+            comparison.observed.abs() - 1.0 / 32.0
+        );
+        // The piling-up lemma is only an approximation, but a close one here
+        assert!(comparison.difference.abs() < 0.01);
+        assert!((comparison.ratio() - 1.0).abs() < 0.3);
+
+        let short = MultiRoundTrail::from_masks(&lat, &PERMUTATION, &masks[..2]).unwrap();
+        assert!(validate_trail(&cipher, &short).is_err());
+    }
+
+    #[test]
+    fn test_build_multiround_trail() {
+        let lat = LinearApproximationTable::from_sbox(&SBOX);
+        assert!(build_multiround_trail(&lat, &PERMUTATION, 0).is_none());
+        assert_eq!(
+            build_multiround_trail(&lat, &PERMUTATION, 1)
+                .unwrap()
+                .bias
+                .abs(),
+            0.375
+        );
+
+        // The best 3-round trail, which an exhaustive search over every mask
+        // confirms, has bias 2^4 * (1/4) * (3/8)^4 = 81/1024 through S14, S24,
+        // and S32 to S34. It is stronger than Heys' 1/32 trail, and the full
+        // codebook agrees with the prediction.
+        let trail = build_multiround_trail(&lat, &PERMUTATION, 3).unwrap();
+        let masks = trail
+            .rounds
+            .iter()
+            .map(|round| (round.input_mask, round.output_mask))
+            .collect::<Vec<(u16, u16)>>();
+        assert_eq!(
+            masks,
+            [(0x000a, 0x0001), (0x0001, 0x0007), (0x0111, 0x0777)]
+        );
+        assert_eq!(trail.bias, 81.0 / 1024.0);
+        assert_eq!((trail.pt_mask, trail.u_mask), (0x000a, 0x0777));
+        assert!(trail.bias.abs() > 1.0 / 32.0);
+        for pair in trail.rounds.windows(2) {
+            assert_eq!(
+                propagate_mask(pair[0].output_mask, &PERMUTATION),
+                pair[1].input_mask
+            );
+        }
+        let cipher = HeysCipher::from_keys(&[0, 0, 0, 0, 0]).unwrap();
+        let (plaintexts, ciphertexts): (Vec<Block>, Vec<Block>) =
+            cipher.encrypt_codebook().unwrap().into_iter().unzip();
+        let observed = cipher
+            .get_bias_signed(
+                &plaintexts,
+                &ciphertexts,
+                &trail.pt_locs(),
+                &trail.u4_locs(),
+            )
+            .unwrap();
+        assert!(observed.abs() >= 1.0 / 32.0);
+        assert_eq!(observed.signum(), trail.bias.signum());
+    }
+
+    #[test]
+    fn test_trail_score() {
+        // Both have bias 1/4, but the output bits of 0x0600 are permuted into
+        // two different S-boxes of the next round
+        let lat = LinearApproximationTable::from_sbox(&SBOX);
+        let narrow = MultiRoundTrail::from_masks(&lat, &PERMUTATION, &[(0x0b00, 0x0400)]).unwrap();
+        let wide = MultiRoundTrail::from_masks(&lat, &PERMUTATION, &[(0x0b00, 0x0600)]).unwrap();
+        assert_eq!(narrow.bias, wide.bias);
+        assert_eq!(trail_score(&narrow), 0.125);
+        assert_eq!(trail_score(&wide), 0.0625);
+
+        // Heys' trail activates S-boxes 2 and 4 of the last round
+        let masks = [(0x0b00, 0x0400), (0x0400, 0x0500), (0x0404, 0x0505)];
+        let heys = MultiRoundTrail::from_masks(&lat, &PERMUTATION, &masks).unwrap();
+        assert_eq!(trail_score(&heys), 1.0 / 128.0);
+    }
+}
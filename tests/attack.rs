@@ -0,0 +1,42 @@
+use cryptanalysis::attack::{run_attack, AttackConfig};
+use cryptanalysis::heys::brute_force_k5;
+use cryptanalysis::io::read_blocks_binstr;
+
+fn fixture_config() -> AttackConfig {
+    return AttackConfig {
+        plaintexts_path: "tests/fixtures/plaintexts.txt".to_string(),
+        ciphertexts_path: "tests/fixtures/ciphertexts.txt".to_string(),
+        pt_locs: vec![5, 7, 8],
+        u4_locs: vec![6, 8, 14, 16],
+        top: 5,
+    };
+}
+
+#[test]
+fn test_run_attack_on_fixtures() {
+    let config = fixture_config();
+    let result = run_attack(config.clone()).unwrap();
+    assert_eq!(result.pairs, 100);
+    assert_eq!(result.rankings.len(), 5);
+
+    let plaintexts = read_blocks_binstr(&config.plaintexts_path).unwrap();
+    let ciphertexts = read_blocks_binstr(&config.ciphertexts_path).unwrap();
+    let expected = brute_force_k5(
+        &plaintexts,
+        &ciphertexts,
+        &config.pt_locs,
+        &config.u4_locs,
+        [0; 4],
+    )
+    .unwrap();
+    assert_eq!(result.rankings, expected[..5]);
+}
+
+#[test]
+fn test_run_attack_missing_file() {
+    let config = AttackConfig {
+        ciphertexts_path: "tests/fixtures/does-not-exist.txt".to_string(),
+        ..fixture_config()
+    };
+    assert!(run_attack(config).is_err());
+}
@@ -0,0 +1,173 @@
+use std::process::Command;
+
+fn cryptanalysis() -> Command {
+    return Command::new(env!("CARGO_BIN_EXE_cryptanalysis"));
+}
+
+#[test]
+fn test_cli_ranks_candidates() {
+    let output = cryptanalysis()
+        .args([
+            "--plaintexts",
+            "tests/fixtures/plaintexts.txt",
+            "--ciphertexts",
+            "tests/fixtures/ciphertexts.txt",
+            "--pt-locs",
+            "5,7,8",
+            "--u4-locs",
+            "6,8,14,16",
+            "--top",
+            "3",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 3, "{stdout}");
+    for line in lines {
+        assert!(line.starts_with("K5 candidate: 0x"), "{line}");
+        assert!(line.contains(", observed bias: "), "{line}");
+    }
+}
+
+#[test]
+fn test_cli_missing_file() {
+    let output = cryptanalysis()
+        .args([
+            "--plaintexts",
+            "tests/fixtures/does-not-exist.txt",
+            "--ciphertexts",
+            "tests/fixtures/ciphertexts.txt",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cli_generate_is_reproducible() {
+    let dir = std::env::temp_dir().join("cryptanalysis_test_cli_generate");
+    std::fs::create_dir_all(&dir).unwrap();
+    let generate = |name: &str| -> (Vec<u8>, Vec<u8>) {
+        let plaintexts = dir.join(format!("{name}_plaintexts.txt"));
+        let ciphertexts = dir.join(format!("{name}_ciphertexts.txt"));
+        let output = cryptanalysis()
+            .args(["generate", "--seed", "42", "--count", "50"])
+            .arg("--out-plaintexts")
+            .arg(&plaintexts)
+            .arg("--out-ciphertexts")
+            .arg(&ciphertexts)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        return (
+            std::fs::read(plaintexts).unwrap(),
+            std::fs::read(ciphertexts).unwrap(),
+        );
+    };
+    let (plaintexts1, ciphertexts1) = generate("first");
+    let (plaintexts2, ciphertexts2) = generate("second");
+    assert_eq!(plaintexts1, plaintexts2);
+    assert_eq!(ciphertexts1, ciphertexts2);
+    assert_eq!(plaintexts1.len(), 50 * 17);
+    assert_ne!(plaintexts1, ciphertexts1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_cli_generate_with_keys() {
+    let dir = std::env::temp_dir().join("cryptanalysis_test_cli_generate_with_keys");
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = cryptanalysis()
+        .args(["generate", "--seed", "0", "--count", "1"])
+        .args(["--keys", "0,0,0,0,0x0f1e"])
+        .arg("--out-plaintexts")
+        .arg(dir.join("plaintexts.txt"))
+        .arg("--out-ciphertexts")
+        .arg(dir.join("ciphertexts.txt"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("0x0000,0x0000,0x0000,0x0000,0x0f1e"),
+        "{stdout}"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_cli_generate_uses_random_keys() {
+    let dir = std::env::temp_dir().join("cryptanalysis_test_cli_generate_random_keys");
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = cryptanalysis()
+        .args(["generate", "--seed", "42", "--count", "1"])
+        .arg("--out-plaintexts")
+        .arg(dir.join("plaintexts.txt"))
+        .arg("--out-ciphertexts")
+        .arg(dir.join("ciphertexts.txt"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let round_keys = cryptanalysis::heys::HeysCipher::random(42)
+        .round_keys()
+        .iter()
+        .map(|round_key| format!("0x{round_key:04x}"))
+        .collect::<Vec<String>>();
+    assert!(stdout.contains(&round_keys.join(",")), "{stdout}");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_cli_partial_ranks_active_nibbles_only() {
+    let output = cryptanalysis()
+        .args([
+            "--plaintexts",
+            "tests/fixtures/plaintexts.txt",
+            "--ciphertexts",
+            "tests/fixtures/ciphertexts.txt",
+            "--partial",
+            "--top",
+            "256",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 256, "{stdout}");
+    for line in lines {
+        let round_key = &line["K5 candidate: 0x".len()..][..4];
+        let round_key = u16::from_str_radix(round_key, 16).unwrap();
+        assert_eq!(round_key & 0xf0f0, 0, "{line}");
+    }
+}
+
+#[test]
+fn test_cli_bias() {
+    let output = cryptanalysis()
+        .args([
+            "bias",
+            "--plaintexts",
+            "tests/fixtures/plaintexts.txt",
+            "--ciphertexts",
+            "tests/fixtures/ciphertexts.txt",
+            "--k5",
+            "0x0706",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let plaintexts =
+        cryptanalysis::io::read_blocks_binstr("tests/fixtures/plaintexts.txt").unwrap();
+    let ciphertexts =
+        cryptanalysis::io::read_blocks_binstr("tests/fixtures/ciphertexts.txt").unwrap();
+    let bias = cryptanalysis::heys::HeysCipher::from_keys(&[0, 0, 0, 0, 0x0706])
+        .unwrap()
+        .get_bias(&plaintexts, &ciphertexts, &[5, 7, 8], &[6, 8, 14, 16])
+        .unwrap();
+    assert_eq!(stdout.trim(), format!("bias: {bias:.08}"));
+}
@@ -0,0 +1,52 @@
+//! The logger is global to the process, so the logging test gets a binary of
+//! its own where no other test can log into it or be slowed down by it
+use cryptanalysis::heys::{brute_force_k5, Block, HeysCipher};
+use std::sync::Mutex;
+
+/// Keeps every log message so that the test can look for them
+struct CapturingLogger(Mutex<Vec<String>>);
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        return true;
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger(Mutex::new(vec![]));
+
+#[test]
+fn test_brute_force_k5_logs_progress() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+    let cipher = HeysCipher::from_keys(&[1, 2, 3, 4, 5]).unwrap();
+    let plaintexts = (0..64).map(Block::new).collect::<Vec<Block>>();
+    let ciphertexts = cipher.encrypt_blocks(&plaintexts).unwrap();
+    let rankings = brute_force_k5(
+        &plaintexts,
+        &ciphertexts,
+        &[5, 7, 8],
+        &[6, 8, 14, 16],
+        [0; 4],
+    )
+    .unwrap();
+
+    let messages = LOGGER.0.lock().unwrap();
+    assert_eq!(messages.len(), 65536 + 1);
+    assert!(messages.contains(&format!(
+        "K5 candidate 0x0005: bias {:.6}",
+        rankings
+            .iter()
+            .find(|(_, round_key)| *round_key == 5)
+            .unwrap()
+            .0
+    )));
+    assert!(messages
+        .iter()
+        .any(|message| message.starts_with("Best of 65536 K5 candidates over 64 PT-CT pairs")));
+}
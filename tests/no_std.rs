@@ -0,0 +1,50 @@
+use std::path::Path;
+use std::process::Command;
+
+/// A bare-metal target without std at all, so that anything in the library
+/// or its dependencies that still links std fails to build
+const NO_STD_TARGET: &str = "thumbv7em-none-eabi";
+
+fn check_without_std(target: Option<&str>) {
+    let mut command = Command::new(env!("CARGO"));
+    command
+        .args(["check", "--lib", "--no-default-features", "--target-dir"])
+        .arg(concat!(env!("CARGO_TARGET_TMPDIR"), "/no_std"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"));
+    if let Some(target) = target {
+        command.args(["--target", target]);
+    }
+    let output = command.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// The library without the `std` feature is `no_std` + `alloc`, so it must
+/// keep compiling on its own
+#[test]
+fn test_builds_without_std() {
+    check_without_std(None);
+}
+
+/// The host target still has std available to dependencies, so the no_std
+/// build is only proven on a target that lacks it. Skipped when the target
+/// is not installed, e.g. `rustup target add thumbv7em-none-eabi`.
+#[test]
+fn test_builds_for_no_std_target() {
+    let sysroot = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .output()
+        .unwrap();
+    let sysroot = String::from_utf8(sysroot.stdout).unwrap();
+    let target_lib = Path::new(sysroot.trim())
+        .join("lib/rustlib")
+        .join(NO_STD_TARGET);
+    if !target_lib.exists() {
+        eprintln!("skipping: {NO_STD_TARGET} is not installed");
+        return;
+    }
+    check_without_std(Some(NO_STD_TARGET));
+}